@@ -0,0 +1,17 @@
+use std::env;
+use std::process::Command;
+
+fn main() {
+    // Reported in the `X-Stainless-Runtime-Version` header.
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|output| output.split_whitespace().nth(1).map(|version| version.to_string()))
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=OPEN_AI_RUSTC_VERSION={version}");
+    println!("cargo:rerun-if-env-changed=RUSTC");
+}
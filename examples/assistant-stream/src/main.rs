@@ -1,15 +1,31 @@
 use std::env;
 
-use futures::stream::StreamExt;
+use async_trait::async_trait;
 use open_ai::{
-    library::assistant_stream::AssistantStream,
+    library::assistant_event_handler::AssistantEventHandler,
     resources::beta::threads::{
-        messages::message_create_params, MessageContentDelta, MessageCreateParams, RunCreateParams,
+        messages::message_create_params, MessageCreateParams, RunCreateParams, Text, TextDelta,
         ThreadCreateParams,
     },
     ClientOptions, OpenAI,
 };
 
+/// Prints the text of the assistant as it is streamed.
+struct Printer;
+
+#[async_trait(?Send)]
+impl AssistantEventHandler for Printer {
+    async fn on_text_created(&mut self, _text: &Text) {
+        print!("assistant > ");
+    }
+
+    async fn on_text_delta(&mut self, delta: &TextDelta, _snapshot: &Text) {
+        if let Some(value) = delta.value.as_ref() {
+            print!("{}", value);
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // OPENAI_API_KEY is required, you can set it in your environment variables.
@@ -50,7 +66,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     println!("{:?}", message);
 
-    let mut run = openai
+    let run = openai
         .beta
         .threads
         .runs
@@ -67,71 +83,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             },
             None,
         )
-        .into_stream();
-
-    while let Some(event) = run.next().await {
-        match event {
-            Ok(AssistantStream::MessageDelta(message)) => {
-                message.delta.content.iter().for_each(|content| {
-                    for delta in content.iter() {
-                        match delta {
-                            MessageContentDelta::TextDeltaBlock(text) => {
-                                if let Some(text) = text.text.as_ref() {
-                                    if let Some(text) = text.value.as_ref() {
-                                        print!("{}", text);
-                                    }
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
-                });
-                // message.content.iter().for_each(|content| {
-                //     for delta in content.iter() {
-                //         match delta {
-                //             MessageContentDelta::TextDeltaBlock( text) => {
-                //                 println!("{:?} > {:?}", message.role, text.text);
-                //             }
-                //             _ => {}
-                //         }
-                //     }
-                // });
-                // println!("chunk: {:?}", message);
-
-                // let first = t.choices.first();
-                // if  first.is_none() {
-                //     continue;
-                // }
-                // let text = first.unwrap().delta.content.as_ref().clone().to_owned();
-                // if let Some(text) = text {
-                //     print!("{}", text);
-                // }
-            }
-            Err(_) => {
-                println!("Error: {:?}", event);
-                break;
-            }
-            _ => continue,
-        }
-    }
+        .with_handler(&mut Printer)
+        .await?;
 
-    // while let Some(event) = completion.next().await {
-    //     match event {
-    //         Ok(t) => {
-    //             if let Some(text) = t
-    //                 .choices
-    //                 .first()
-    //                 .and_then(|choice| choice.delta.content.as_ref().cloned())
-    //             {
-    //                 print!("{}", text);
-    //             }
-    //         }
-    //         Err(_) => {
-    //             println!("Error: {:?}", event);
-    //             // break;
-    //         }
-    //     }
-    // }
+    println!("\n{:?}", run.status);
 
     Ok(())
 }
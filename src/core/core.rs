@@ -1,29 +1,45 @@
 use std::cell::RefCell;
 use reqwest::header::{HeaderMap, HeaderValue};
-use reqwest::{Client, Method, Request, RequestBuilder, Response, Url};
+use reqwest::{Client, Method, Request, RequestBuilder, Response, StatusCode, Url};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::error::Error;
+use std::future::Future;
+use crate::error::Error;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 // use futures::executor::block_on;
 use serde_json::Value;
 use tokio::time::sleep;
-use crate::pagination::{Page, CursorPage, CursorPageResponse};
+use uuid::Uuid;
+use crate::pagination::{Page, CursorItem, CursorPage, CursorPageResponse};
 pub use crate::core::request_options::*;
+use crate::core::multipart::MultipartForm;
+use crate::core::logging::{self, LogLevel, RequestSpan};
+use crate::core::metrics::Metrics;
+use crate::core::middleware::{Middleware, RequestContext};
+use crate::core::platform;
+use crate::core::transport::{ReqwestTransport, Transport};
 use crate::core::streaming::{APIFuture, APIFutureState};
 use crate::resource::APIResource;
 
-pub type APIPromise<T> = tokio::task::JoinHandle<Result<T, Box<dyn Error>>>;
+pub type APIPromise<T> = tokio::task::JoinHandle<Result<T, Error>>;
 
 #[derive(Clone, Debug)]
 pub struct APIClient {
     pub base_url: String,
     pub max_retries: u32,
     pub timeout: Duration,
+    /// Builds the requests; they are sent through `transport`.
     pub client: Client,
+    pub transport: Arc<dyn Transport>,
+    pub middleware: Vec<Arc<dyn Middleware>>,
     pub additional_auth_headers: Option<Headers>,
+    pub custom_headers: Option<Headers>,
+    pub platform_headers: Option<Headers>,
+    pub default_query: Option<HashMap<String, String>>,
+    pub stream_idle_timeout: Option<Duration>,
+    pub metrics: Metrics,
 }
 
 impl APIClient {
@@ -32,12 +48,19 @@ impl APIClient {
             base_url,
             max_retries,
             timeout,
+            transport: Arc::new(ReqwestTransport::new(client.clone())),
+            middleware: Vec::new(),
             client,
             additional_auth_headers: None,
+            custom_headers: None,
+            platform_headers: Some(platform::platform_headers()),
+            default_query: None,
+            stream_idle_timeout: None,
+            metrics: Metrics::default(),
         }
     }
 
-    pub fn auth_headers<Req: Default + Clone + Serialize>(&self, opts: &FinalRequestOptions<Req>) -> Headers {
+    pub fn auth_headers<Req: Default + Clone + Serialize>(&self, _opts: &FinalRequestOptions<Req>) -> Headers {
         let mut headers: Headers = HashMap::new();
 
         if let Some(self_headers) = &self.additional_auth_headers {
@@ -48,14 +71,6 @@ impl APIClient {
             }
         }
 
-        if let Some(request_headers) = &opts.headers {
-            for (key, value) in request_headers {
-                if let Some(value) = value {
-                    headers.insert(key.clone(), Some(value.clone()));
-                }
-            }
-        }
-
         headers
     }
 
@@ -66,24 +81,261 @@ impl APIClient {
         //     'User-Agent': this.getUserAgent(),
         //     ...getPlatformHeaders(),
         //     ...this.authHeaders(opts),
+        //     ...this._options.defaultHeaders,
         // };
 
         let mut headers: Headers = HashMap::new();
-        let mut auth_headers: Headers = self.auth_headers(opts);
 
-        headers.insert("Accept".to_string(), Some("application/json".to_string()));
-        headers.insert("Content-Type".to_string(), Some("application/json".to_string()));
-        headers.insert("User-Agent".to_string(), Some("this.getUserAgent()".to_string()));
+        let accept = if opts.binary_response.unwrap_or(false) { "application/binary" } else { "application/json" };
+        let content_type = match &opts.multipart {
+            Some(form) => form.content_type(),
+            None => "application/json".to_string(),
+        };
+        headers.insert("Accept".to_string(), Some(accept.to_string()));
+        headers.insert("Content-Type".to_string(), Some(content_type));
+        headers.insert("User-Agent".to_string(), Some(platform::user_agent()));
 
-        for (key, value) in auth_headers {
-            if let Some(value) = value {
-                headers.insert(key.clone(), Some(value.clone()));
-            }
+        if let Some(platform_headers) = &self.platform_headers {
+            merge_headers(&mut headers, platform_headers);
+        }
+
+        merge_headers(&mut headers, &self.auth_headers(opts));
+
+        if let Some(custom_headers) = &self.custom_headers {
+            merge_headers(&mut headers, custom_headers);
         }
 
         headers
     }
-    
+
+    /// Merges the headers of the request over the default headers of the client.
+    ///
+    /// A `None` value in the request headers removes the default header of the same name.
+    pub fn build_headers<Req: Default + Clone + Serialize>(&self, opts: &FinalRequestOptions<Req>) -> Headers {
+        let mut headers = self.default_headers(opts);
+
+        if let Some(request_headers) = &opts.headers {
+            merge_headers(&mut headers, request_headers);
+        }
+
+        headers
+    }
+
+    /// Merges the query of the request over the default query of the client.
+    ///
+    /// A `None` value in the request query removes the default param of the same name.
+    pub fn build_query<Req: Default + Clone + Serialize>(&self, opts: &FinalRequestOptions<Req>) -> Vec<(String, String)> {
+        let mut params: Vec<(String, String)> = self.default_query.iter()
+            .flatten()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+
+        if let Some(Value::Object(query)) = opts.query.as_ref().and_then(|query| serde_json::to_value(query).ok()) {
+            for (key, value) in query {
+                let nested = format!("{key}[");
+                params.retain(|(name, _)| name != &key && !name.starts_with(&nested));
+                stringify_query_value(&mut params, key, value);
+            }
+        }
+
+        params
+    }
+
+    /// Sends the request, retrying connection errors, timeouts and retryable responses
+    /// with an exponential backoff until `max_retries` is exhausted.
+    ///
+    /// Each attempt must receive the response headers within `timeout`. The `multipart`
+    /// body is encoded again for each of them, reopening the files it streams.
+    pub(crate) async fn send_with_retries(
+        &self,
+        request: RequestBuilder,
+        multipart: Option<&MultipartForm>,
+        max_retries: u32,
+        timeout: Duration,
+    ) -> Result<Response, Error> {
+        let mut retries_remaining = max_retries;
+
+        loop {
+            let attempt = match request.try_clone() {
+                Some(attempt) => attempt,
+                // Streaming bodies can't be replayed, so they only get a single attempt.
+                None => return self.send_with_timeout(request, max_retries - retries_remaining, timeout).await,
+            };
+
+            let attempt = match multipart {
+                Some(form) => {
+                    // A missing file fails the request instead of the body, which would be retried.
+                    form.check_files().await?;
+                    attempt.body(form.body())
+                }
+                None => attempt,
+            };
+
+            let attempt = match &self.platform_headers {
+                Some(_) => attempt.header("X-Stainless-Retry-Count", (max_retries - retries_remaining).to_string()),
+                None => attempt,
+            };
+
+            match self.send_with_timeout(attempt, max_retries - retries_remaining, timeout).await {
+                Ok(response) => {
+                    if retries_remaining == 0 || !Self::should_retry(&response) {
+                        return Ok(response);
+                    }
+                    let timeout = Self::retry_timeout(retries_remaining, max_retries, Some(response.headers()));
+                    logging::log(LogLevel::Info, || format!(
+                        "retrying after status {} in {}ms, {retries_remaining} attempts remaining",
+                        response.status().as_u16(),
+                        timeout.as_millis(),
+                    ));
+                    retries_remaining -= 1;
+                    sleep(timeout).await;
+                }
+                Err(err) => {
+                    let retryable = matches!(err, Error::Connection { .. } | Error::ConnectionTimeout { .. });
+                    if retries_remaining == 0 || !retryable {
+                        return Err(err);
+                    }
+                    let timeout = Self::retry_timeout(retries_remaining, max_retries, None);
+                    logging::log(LogLevel::Info, || format!(
+                        "retrying after \"{err}\" in {}ms, {retries_remaining} attempts remaining",
+                        timeout.as_millis(),
+                    ));
+                    retries_remaining -= 1;
+                    sleep(timeout).await;
+                }
+            }
+        }
+    }
+
+    async fn send_with_timeout(&self, request: RequestBuilder, retry_count: u32, timeout: Duration) -> Result<Response, Error> {
+        let mut request = request.build()?;
+        let context = RequestContext {
+            method: request.method().clone(),
+            url: request.url().clone(),
+            retry_count,
+            started_at: Instant::now(),
+        };
+
+        for middleware in &self.middleware {
+            middleware.before_request(&context, &mut request).await?;
+        }
+
+        logging::record_attempt(retry_count);
+        logging::log(LogLevel::Info, || format!("request {} {} (retry {retry_count})", context.method, context.url));
+        logging::log(LogLevel::Debug, || format!(
+            "request headers: {}; body: {}",
+            logging::redacted_headers(request.headers()),
+            request.body().and_then(|body| body.as_bytes()).map(String::from_utf8_lossy).unwrap_or_default(),
+        ));
+
+        let result = match tokio::time::timeout(timeout, self.transport.send(request)).await {
+            Ok(response) => response,
+            Err(_) => Err(Error::ConnectionTimeout { idempotency_key: None }),
+        };
+
+        match result {
+            Ok(mut response) => {
+                logging::record_response(&response);
+                logging::log(LogLevel::Info, || format!(
+                    "response {} {} {} in {}ms",
+                    context.method,
+                    context.url,
+                    response.status().as_u16(),
+                    context.started_at.elapsed().as_millis(),
+                ));
+                logging::log(LogLevel::Debug, || format!("response headers: {}", logging::redacted_headers(response.headers())));
+                for middleware in self.middleware.iter().rev() {
+                    middleware.after_response(&context, &mut response).await?;
+                }
+                Ok(response)
+            }
+            Err(error) => {
+                logging::log(LogLevel::Warn, || format!("request {} {} failed: {error}", context.method, context.url));
+                for middleware in self.middleware.iter().rev() {
+                    middleware.on_error(&context, &error).await;
+                }
+                Err(error)
+            }
+        }
+    }
+
+    fn default_idempotency_key() -> String {
+        format!("stainless-rust-retry-{}", Uuid::new_v4())
+    }
+
+    /// Resolves `future`, unless `signal` is cancelled first.
+    pub(crate) async fn abortable<T>(
+        signal: Option<CancellationToken>,
+        future: impl Future<Output=Result<T, Error>>,
+    ) -> Result<T, Error> {
+        match signal {
+            Some(signal) => tokio::select! {
+                biased;
+                _ = signal.cancelled() => Err(Error::UserAbort),
+                result = future => result,
+            },
+            None => future.await,
+        }
+    }
+
+    fn should_retry(response: &Response) -> bool {
+        // Note this is not a standard header.
+        match response.headers().get("x-should-retry").and_then(|value| value.to_str().ok()) {
+            // If the server explicitly says whether or not to retry, obey.
+            Some("true") => return true,
+            Some("false") => return false,
+            _ => {}
+        }
+
+        let status = response.status();
+
+        // Retry on request timeouts, lock timeouts, rate limits and internal errors.
+        status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::CONFLICT
+            || status == StatusCode::TOO_MANY_REQUESTS
+            || status.is_server_error()
+    }
+
+    fn retry_timeout(retries_remaining: u32, max_retries: u32, headers: Option<&HeaderMap>) -> Duration {
+        let header = |name: &str| headers.and_then(|headers| headers.get(name)).and_then(|value| value.to_str().ok());
+
+        // Note the `retry-after-ms` header may not be standard, but is a good idea and we'd like proactive support for it.
+        let retry_after = header("retry-after-ms")
+            .and_then(|value| value.parse::<f64>().ok())
+            .and_then(|millis| Duration::try_from_secs_f64(millis / 1000.0).ok())
+            .or_else(|| {
+                let value = header("retry-after")?;
+                match value.parse::<f64>() {
+                    Ok(seconds) => Duration::try_from_secs_f64(seconds).ok(),
+                    Err(_) => httpdate::parse_http_date(value)
+                        .ok()
+                        .map(|date| date.duration_since(SystemTime::now()).unwrap_or_default()),
+                }
+            });
+
+        // If the API asks us to wait a certain amount of time (and it's a reasonable amount),
+        // just do what it says, but otherwise calculate a default.
+        match retry_after {
+            Some(timeout) if timeout < Duration::from_secs(60) => timeout,
+            _ => Self::default_retry_timeout(retries_remaining, max_retries),
+        }
+    }
+
+    fn default_retry_timeout(retries_remaining: u32, max_retries: u32) -> Duration {
+        const INITIAL_RETRY_DELAY: f64 = 0.5;
+        const MAX_RETRY_DELAY: f64 = 8.0;
+
+        let num_retries = max_retries.saturating_sub(retries_remaining);
+
+        // Apply exponential backoff, but not more than the max.
+        let sleep_seconds = (INITIAL_RETRY_DELAY * 2f64.powi(num_retries as i32)).min(MAX_RETRY_DELAY);
+
+        // Apply some jitter, take up to at most 25 percent of the retry time.
+        let jitter = 1.0 - rand::random::<f64>() * 0.25;
+
+        Duration::from_secs_f64(sleep_seconds * jitter)
+    }
+
     pub fn get<
         'a,
         Req: Default + Clone + Serialize,
@@ -93,7 +345,7 @@ impl APIClient {
         &'a self,
         path: &str,
         opts: Option<RequestOptions<Req>>,
-    // ) -> Result<Rsp, Box<dyn Error>> {
+    // ) -> Result<Rsp, Error> {
     ) -> APIFuture<Req, ItemNonStreaming, ItemStreaming> {
         self.method_request(Method::GET, path, opts)
     }
@@ -107,7 +359,7 @@ impl APIClient {
         &'a self,
         path: &str,
         opts: Option<RequestOptions<Req>>,
-    // ) -> Result<Rsp, Box<dyn Error>> {
+    // ) -> Result<Rsp, Error> {
     ) -> APIFuture<Req, ItemNonStreaming, ItemStreaming> {
         self.method_request(Method::POST, path, opts)
     }
@@ -121,12 +373,12 @@ impl APIClient {
         &'a self,
         path: &str,
         opts: Option<RequestOptions<Req>>,
-    // ) -> Result<Rsp, Box<dyn Error>> {
+    // ) -> Result<Rsp, Error> {
     ) -> APIFuture<Req, ItemNonStreaming, ItemStreaming> {
             self.method_request(Method::DELETE, path, opts)
     }
 
-    pub async fn get_api_list<Req: Default + Clone + Serialize, Item: 'static + for<'de> Deserialize<'de> + Clone>(
+    pub async fn get_api_list<Req: Default + Clone + Serialize + for<'de> Deserialize<'de>, Item: 'static + for<'de> Deserialize<'de> + Clone + CursorItem>(
         &self,
         path: &str,
         // Page: new (...args: any[]) => PageImpl,
@@ -136,12 +388,12 @@ impl APIClient {
             FinalRequestOptions<Req>,
         ) -> CursorPage<Req, Item>,
         opts: Option<RequestOptions<Req>>,
-    ) -> Result<CursorPage<Req, Item>, Box<dyn Error>> {
+    ) -> Result<CursorPage<Req, Item>, Error> {
         let opts: FinalRequestOptions<Req> = FinalRequestOptions::new(&Method::GET, path, opts.unwrap_or_default());
         self.request_api_list::<Req, Item>(page, opts).await
     }
 
-    pub async fn request_api_list<Req: Default + Clone + Serialize, Item: 'static + for<'de> Deserialize<'de> + Clone /*, PageImpl: Page<Req, Item> */>(
+    pub async fn request_api_list<Req: Default + Clone + Serialize + for<'de> Deserialize<'de>, Item: 'static + for<'de> Deserialize<'de> + Clone + CursorItem /*, PageImpl: Page<Req, Item> */>(
         &self,
         // Page: new (...args: ConstructorParameters<typeof Page>) => PageClass,
         page: impl FnOnce(
@@ -150,7 +402,7 @@ impl APIClient {
             FinalRequestOptions<Req>,
         ) -> CursorPage<Req, Item>,
         options: FinalRequestOptions<Req>,
-    ) -> Result<CursorPage<Req, Item>, Box<dyn Error>>
+    ) -> Result<CursorPage<Req, Item>, Error>
     // where PageImpl: Page<Req, Item>,
     {
         let request = self.make_request(options, None).await;
@@ -158,11 +410,11 @@ impl APIClient {
         request
     }
 
-    async fn make_request<Req: Default + Clone + Serialize, Item: 'static + for<'de> Deserialize<'de> + Clone>(
+    async fn make_request<Req: Default + Clone + Serialize + for<'de> Deserialize<'de>, Item: 'static + for<'de> Deserialize<'de> + Clone + CursorItem>(
         &self,
         opts: FinalRequestOptions<Req>,
         retries_remaining: Option<()>
-    ) -> Result<CursorPage<Req, Item>, Box<dyn Error>> {
+    ) -> Result<CursorPage<Req, Item>, Error> {
         let api_future = self.request::<Req, CursorPageResponse<Item>, String>(opts.clone());
         let response = api_future.await?;
         let cursor_page = CursorPage::new(
@@ -186,7 +438,7 @@ impl APIClient {
         method: Method,
         path: &str,
         opts: Option<RequestOptions<Req>>,
-    // ) -> Result<Rsp, Box<dyn Error>> {
+    // ) -> Result<Rsp, Error> {
     ) -> APIFuture<Req, ItemNonStreaming, ItemStreaming> {
         let opts: FinalRequestOptions<Req> = FinalRequestOptions::new(&method, path, opts.unwrap_or_default());
         self.request(opts)
@@ -201,6 +453,16 @@ impl APIClient {
         &'a self,
         opts: FinalRequestOptions<Req>,
     ) -> APIFuture<Req, ItemNonStreaming, ItemStreaming> {
+        let mut opts = opts;
+
+        // The key is generated once per logical request so every retry sends the same one.
+        // GETs are idempotent already, and drop the key of the options they are copied from.
+        if opts.method == Method::GET {
+            opts.idempotency_key = None;
+        } else if opts.idempotency_key.is_none() {
+            opts.idempotency_key = Some(Self::default_idempotency_key());
+        }
+
         let url = format!("{}{}", self.base_url, opts.path);
         // let request_builder = self.client.request(opts.method.clone(), &url);
 
@@ -222,8 +484,23 @@ impl APIClient {
         // loop {
         let request_builder = self.client.request(opts.method.clone(), &url);
 
+        let query = self.build_query(&opts);
+        let request_builder = if query.is_empty() {
+            request_builder
+        } else {
+            request_builder.query(&query)
+        };
+
+        // Streams and binary downloads are only bound by the timeout until the response
+        // headers arrive; stream events are guarded by the idle timeout instead.
+        let request_builder = if opts.stream.unwrap_or(false) || opts.binary_response.unwrap_or(false) {
+            request_builder
+        } else {
+            request_builder.timeout(opts.timeout.unwrap_or(self.timeout))
+        };
+
         // begin
-        let headers = self.default_headers(&opts);
+        let headers = self.build_headers(&opts);
 
         let request_builder = headers.into_iter().fold(request_builder, |rb, (key, value)| {
             if let Some(value) = value {
@@ -232,9 +509,17 @@ impl APIClient {
                 rb
             }
         });
+
+        let request_builder = match &opts.idempotency_key {
+            Some(idempotency_key) => request_builder.header("Idempotency-Key", idempotency_key),
+            None => request_builder,
+        };
         // end
 
-        let request_builder = {
+        // Multipart bodies are set by every attempt of the request, see `send_with_retries`.
+        let request_builder = if opts.multipart.is_some() {
+            request_builder
+        } else {
             let body = match &opts.body {
                 Some(body) => body.clone(),
                 None => Req::default(),
@@ -253,6 +538,8 @@ impl APIClient {
             request: Some(request_builder),
             state: APIFutureState::Init,
             streaming_state: None,
+            span: RequestSpan::new(&opts),
+            metrics: self.metrics.start(&self.base_url, &opts),
             request_options: opts,
         };
 
@@ -317,7 +604,7 @@ impl APIClient {
         //                 // block_on(sleep(delay)); // .await;
         //                 delay = delay * 2;
         //             } else {
-        //                 // return Err(Box::new(err));
+        //                 // return Err(err.into());
         //                 panic!("{}", err);
         //             }
         //         }
@@ -346,6 +633,34 @@ impl APIClient {
 
 pub type Headers = HashMap<String, Option<String>>;
 
+/// Inserts `source` into `target`, replacing headers of the same name regardless of their case.
+fn merge_headers(target: &mut Headers, source: &Headers) {
+    for (key, value) in source {
+        target.retain(|existing, _| !existing.eq_ignore_ascii_case(key));
+        target.insert(key.clone(), value.clone());
+    }
+}
+
+/// Appends `value` to `params` the way `qs` does in the TS SDK: arrays as `key[]`
+/// and objects as `key[field]`, skipping `null` values.
+pub(crate) fn stringify_query_value(params: &mut Vec<(String, String)>, key: String, value: Value) {
+    match value {
+        Value::Null => {}
+        Value::String(value) => params.push((key, value)),
+        Value::Array(values) => {
+            for value in values {
+                stringify_query_value(params, format!("{key}[]"), value);
+            }
+        }
+        Value::Object(fields) => {
+            for (field, value) in fields {
+                stringify_query_value(params, format!("{key}[{field}]"), value);
+            }
+        }
+        value => params.push((key, value.to_string())),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Logprobs {
     pub tokens: Vec<String>,
@@ -367,7 +682,7 @@ pub enum PageInfo {
 }
 
 // #[tokio::main]
-// async fn main() -> Result<(), Box<dyn Error>> {
+// async fn main() -> Result<(), Error> {
 //     let api_key = "your_openai_api_key_here".to_string();
 //     let response = example_completion(api_key).await?;
 //     println!("{:#?}", response);
@@ -407,7 +722,7 @@ pub enum PageInfo {
 //     pub controller: Arc<Mutex<Option<oneshot::Sender<()>>>>,
 // }
 //
-// pub type APIPromise<T> = tokio::task::JoinHandle<Result<T, Box<dyn Error>>>;
+// pub type APIPromise<T> = tokio::task::JoinHandle<Result<T, Error>>;
 //
 // #[derive(Default, Clone, Debug)]
 // pub struct APIClient {
@@ -480,7 +795,7 @@ pub enum PageInfo {
 //         &self,
 //         path: &str,
 //         opts: Option<RequestOptions<Req>>,
-//     ) -> Result<Rsp, Box<dyn Error>> {
+//     ) -> Result<Rsp, Error> {
 //         self.method_request(Method::GET, path, opts).await
 //     }
 //
@@ -488,7 +803,7 @@ pub enum PageInfo {
 //         &self,
 //         path: &str,
 //         opts: Option<RequestOptions<Req>>,
-//     ) -> Result<Rsp, Box<dyn Error>> {
+//     ) -> Result<Rsp, Error> {
 //         self.method_request(Method::POST, path, opts).await
 //     }
 //
@@ -496,7 +811,7 @@ pub enum PageInfo {
 //         &self,
 //         path: &str,
 //         opts: Option<RequestOptions<Req>>,
-//     ) -> Result<Rsp, Box<dyn Error>> {
+//     ) -> Result<Rsp, Error> {
 //         self.method_request(Method::DELETE, path, opts).await
 //     }
 //
@@ -558,7 +873,7 @@ pub enum PageInfo {
 //         method: Method,
 //         path: &str,
 //         opts: Option<RequestOptions<Req>>,
-//     ) -> Result<Rsp, Box<dyn Error>> {
+//     ) -> Result<Rsp, Error> {
 //         let url = format!("{}/{}", self.base_url, path);
 //         let mut retries_remaining = self.max_retries;
 //         let mut delay = Duration::from_millis(500);
@@ -621,7 +936,7 @@ pub enum PageInfo {
 //                         sleep(delay).await;
 //                         delay = delay * 2;
 //                     } else {
-//                         return Err(Box::new(err));
+//                         return Err(err.into());
 //                     }
 //                 }
 //             }
@@ -836,4 +1151,307 @@ pub enum PageInfo {
 //                  "type":""
 //              }}],
 //              "model":"gpt-4o"
-//          }
\ No newline at end of file
+//          }
+#[cfg(test)]
+mod tests {
+    use futures::TryStreamExt;
+    use crate::core::{FilePart, InMemoryTransport};
+    use super::*;
+
+    fn response(status: u16, headers: &[(&str, &str)]) -> Response {
+        let mut builder = http::Response::builder().status(status);
+        for (key, value) in headers {
+            builder = builder.header(*key, *value);
+        }
+        Response::from(builder.body("").unwrap())
+    }
+
+    #[test]
+    fn should_retry_transient_statuses() {
+        for status in [408, 409, 429, 500, 502, 503] {
+            assert!(APIClient::should_retry(&response(status, &[])), "{status}");
+        }
+        for status in [200, 400, 401, 404, 422] {
+            assert!(!APIClient::should_retry(&response(status, &[])), "{status}");
+        }
+        assert!(APIClient::should_retry(&response(400, &[("x-should-retry", "true")])));
+        assert!(!APIClient::should_retry(&response(500, &[("x-should-retry", "false")])));
+    }
+
+    #[test]
+    fn retry_timeout_honors_retry_after_headers() {
+        let headers = response(429, &[("retry-after-ms", "1500")]).headers().clone();
+        assert_eq!(APIClient::retry_timeout(2, 2, Some(&headers)), Duration::from_millis(1500));
+
+        let headers = response(429, &[("retry-after", "3")]).headers().clone();
+        assert_eq!(APIClient::retry_timeout(2, 2, Some(&headers)), Duration::from_secs(3));
+
+        // Unreasonable values fall back to the default backoff.
+        let headers = response(429, &[("retry-after", "120")]).headers().clone();
+        assert!(APIClient::retry_timeout(2, 2, Some(&headers)) <= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn default_retry_timeout_backs_off_exponentially() {
+        let first = APIClient::default_retry_timeout(2, 2);
+        assert!(first > Duration::from_millis(375) && first <= Duration::from_millis(500));
+
+        let second = APIClient::default_retry_timeout(1, 2);
+        assert!(second > Duration::from_millis(750) && second <= Duration::from_millis(1000));
+
+        let capped = APIClient::default_retry_timeout(0, 10);
+        assert!(capped > Duration::from_secs(6) && capped <= Duration::from_secs(8));
+    }
+
+    #[tokio::test]
+    async fn abortable_stops_when_signal_is_cancelled() {
+        let signal = CancellationToken::new();
+        signal.cancel();
+
+        let result = APIClient::abortable(Some(signal), futures::future::pending::<Result<(), Error>>()).await;
+        assert!(matches!(result, Err(Error::UserAbort)));
+
+        let result = APIClient::abortable(Some(CancellationToken::new()), async { Ok(1) }).await;
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    #[test]
+    fn post_requests_carry_a_stable_idempotency_key() {
+        let client = APIClient::new("http://localhost".to_string(), 2, Duration::from_secs(10), Client::new());
+
+        let future = client.post::<(), (), ()>("/threads", None);
+        let idempotency_key = future.request_options.idempotency_key.clone().unwrap();
+        let request = future.request.unwrap().build().unwrap();
+        assert!(idempotency_key.starts_with("stainless-rust-retry-"));
+        assert_eq!(request.headers()["Idempotency-Key"], idempotency_key.as_str());
+
+        let future = client.post::<(), (), ()>("/threads", Some(RequestOptions {
+            idempotency_key: Some("my-key".to_string()),
+            ..Default::default()
+        }));
+        let request = future.request.unwrap().build().unwrap();
+        assert_eq!(request.headers()["Idempotency-Key"], "my-key");
+
+        let future = client.get::<(), (), ()>("/threads/thread_abc", None);
+        assert!(future.request_options.idempotency_key.is_none());
+    }
+
+    #[test]
+    fn get_requests_never_carry_an_idempotency_key() {
+        let client = APIClient::new("http://localhost".to_string(), 2, Duration::from_secs(10), Client::new());
+
+        // Like the options of a create call, copied into the GETs polling its result.
+        let options = RequestOptions::<()> {
+            idempotency_key: Some("my-key".to_string()),
+            ..Default::default()
+        };
+        let future = client.get::<(), (), ()>("/threads/thread_abc/runs/run_abc", Some(options.convert(None)));
+        let request = future.request.unwrap().build().unwrap();
+        assert!(future.request_options.idempotency_key.is_none());
+        assert!(!request.headers().contains_key("Idempotency-Key"));
+    }
+
+    #[test]
+    fn request_headers_and_query_override_client_defaults() {
+        let mut client = APIClient::new("http://localhost".to_string(), 2, Duration::from_secs(10), Client::new());
+        client.custom_headers = Some(HashMap::from([
+            ("OpenAI-Project".to_string(), Some("proj_default".to_string())),
+            ("X-Team".to_string(), Some("search".to_string())),
+        ]));
+        client.default_query = Some(HashMap::from([
+            ("api-version".to_string(), "2024-06-01".to_string()),
+            ("tag".to_string(), "default".to_string()),
+        ]));
+
+        #[derive(Default, Clone, Serialize)]
+        struct Query {
+            tag: Option<String>,
+            include: Vec<String>,
+            limit: u32,
+        }
+
+        let future = client.get::<Query, (), ()>("/files", Some(RequestOptions {
+            query: Some(Query { tag: None, include: vec!["a".to_string(), "b".to_string()], limit: 5 }),
+            headers: Some(HashMap::from([
+                ("openai-project".to_string(), Some("proj_override".to_string())),
+                ("X-Team".to_string(), None),
+            ])),
+            ..Default::default()
+        }));
+        let request = future.request.unwrap().build().unwrap();
+
+        assert_eq!(request.headers()["OpenAI-Project"], "proj_override");
+        assert!(request.headers().get("X-Team").is_none());
+
+        let mut query: Vec<_> = request.url().query_pairs().into_owned().collect();
+        query.sort();
+        assert_eq!(query, vec![
+            ("api-version".to_string(), "2024-06-01".to_string()),
+            ("include[]".to_string(), "a".to_string()),
+            ("include[]".to_string(), "b".to_string()),
+            ("limit".to_string(), "5".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn default_headers_describe_the_client() {
+        let mut client = APIClient::new("http://localhost".to_string(), 2, Duration::from_secs(10), Client::new());
+
+        let request = client.get::<(), (), ()>("/models", None).request.unwrap().build().unwrap();
+        assert_eq!(request.headers()["User-Agent"], format!("open_ai/{}", env!("CARGO_PKG_VERSION")).as_str());
+        assert_eq!(request.headers()["X-Stainless-Lang"], "rust");
+        assert!(request.headers().contains_key("X-Stainless-Runtime-Version"));
+
+        client.platform_headers = None;
+        let request = client.get::<(), (), ()>("/models", None).request.unwrap().build().unwrap();
+        assert!(request.headers().keys().all(|name| !name.as_str().starts_with("x-stainless")));
+    }
+
+    fn in_memory_client(transport: &Arc<InMemoryTransport>) -> APIClient {
+        APIClient { max_retries: 2, ..APIClient::with_transport(transport) }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_reuse_the_idempotency_key() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_connection_error()
+            .push_response(500, &[], "")
+            .push_json(&serde_json::json!({"id": "thread_abc"}));
+        let client = in_memory_client(&transport);
+
+        let thread: Value = client.post::<(), Value, ()>("/threads", None).await.unwrap();
+        assert_eq!(thread["id"], "thread_abc");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        let keys: Vec<_> = requests.iter().map(|request| request.headers["Idempotency-Key"].clone()).collect();
+        assert!(keys.iter().all(|key| key == keys[0]));
+        let retry_counts: Vec<_> = requests.iter().map(|request| request.headers["X-Stainless-Retry-Count"].clone()).collect();
+        assert_eq!(retry_counts, ["0", "1", "2"]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_reopen_the_files_of_multipart_bodies() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_response(500, &[], "")
+            .push_json(&serde_json::json!({"id": "file-abc"}));
+        let client = in_memory_client(&transport);
+        let path = std::env::temp_dir().join(format!("open_ai-{}.jsonl", Uuid::new_v4()));
+        std::fs::write(&path, "{}").unwrap();
+        let form = MultipartForm::new()
+            .text("purpose", "batch")
+            .file("file", FilePart::from_path(&path).await.unwrap());
+
+        let file: Value = client.post::<(), Value, ()>("/files", Some(RequestOptions {
+            multipart: Some(form.clone()),
+            ..Default::default()
+        })).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(file["id"], "file-abc");
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.headers["Content-Type"] == form.content_type().as_str()));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn missing_files_of_multipart_bodies_fail_without_retrying() {
+        let transport = Arc::new(InMemoryTransport::new());
+        let client = in_memory_client(&transport);
+        let path = std::env::temp_dir().join(format!("open_ai-{}.jsonl", Uuid::new_v4()));
+        std::fs::write(&path, "{}").unwrap();
+        let form = MultipartForm::new().file("file", FilePart::from_path(&path).await.unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let error = client.post::<(), Value, ()>("/files", Some(RequestOptions {
+            multipart: Some(form),
+            ..Default::default()
+        })).await.unwrap_err();
+
+        match error {
+            Error::OpenAI(message) => assert!(message.starts_with(&format!("Failed to read {}", path.display()))),
+            error => panic!("unexpected error: {error:?}"),
+        }
+        assert!(transport.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn error_responses_are_not_deserialized_as_the_success_type() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_response(
+            401,
+            &[("x-request-id", "req_123")],
+            r#"{"error": {"message": "Incorrect API key provided", "type": "invalid_request_error", "param": null, "code": "invalid_api_key"}}"#,
+        );
+        let client = in_memory_client(&transport);
+
+        let error = client.get::<(), Value, ()>("/models", None).await.unwrap_err();
+        assert!(matches!(error, Error::Authentication(_)));
+        assert_eq!(error.request_id(), Some("req_123"));
+        assert_eq!(transport.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn into_stream_decodes_server_sent_events() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_response(
+            200,
+            &[("content-type", "text/event-stream")],
+            "data: {\"n\": 1}\n\ndata: {\"n\": 2}\n\ndata: [DONE]\n\n",
+        );
+        let client = in_memory_client(&transport);
+
+        let stream = client.post::<(), (), Value>("/chat/completions", Some(RequestOptions {
+            stream: Some(true),
+            ..Default::default()
+        })).into_stream();
+        let chunks: Vec<Value> = stream.try_collect().await.unwrap();
+        assert_eq!(chunks, vec![serde_json::json!({"n": 1}), serde_json::json!({"n": 2})]);
+    }
+
+    #[derive(Debug)]
+    struct Recorder {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Middleware for Recorder {
+        async fn before_request(&self, context: &RequestContext, request: &mut Request) -> Result<(), Error> {
+            self.calls.lock().unwrap().push(format!("{} before #{}", self.name, context.retry_count));
+            request.headers_mut().insert("x-signed-by", self.name.parse().unwrap());
+            Ok(())
+        }
+
+        async fn after_response(&self, _context: &RequestContext, response: &mut Response) -> Result<(), Error> {
+            self.calls.lock().unwrap().push(format!("{} after {}", self.name, response.status().as_u16()));
+            Ok(())
+        }
+
+        async fn on_error(&self, _context: &RequestContext, error: &Error) {
+            self.calls.lock().unwrap().push(format!("{} error {error}", self.name));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn middleware_wraps_every_attempt() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_connection_error().push_json(&serde_json::json!({}));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut client = in_memory_client(&transport);
+        client.middleware = vec![
+            Arc::new(Recorder { name: "outer", calls: calls.clone() }),
+            Arc::new(Recorder { name: "inner", calls: calls.clone() }),
+        ];
+
+        client.get::<(), Value, ()>("/models", None).await.unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), [
+            "outer before #0", "inner before #0", "inner error Connection error.", "outer error Connection error.",
+            "outer before #1", "inner before #1", "inner after 200", "outer after 200",
+        ]);
+        assert_eq!(transport.requests()[1].headers["x-signed-by"], "inner");
+    }
+}
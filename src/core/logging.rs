@@ -0,0 +1,229 @@
+use std::future::Future;
+use lazy_static::lazy_static;
+use reqwest::header::{HeaderMap, HeaderName};
+use reqwest::Response;
+use serde::Serialize;
+use crate::core::FinalRequestOptions;
+
+/// The levels accepted by the `OPENAI_LOG` environment variable, from the quietest to the
+/// most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    fn parse(value: &str) -> Option<LogLevel> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "" => Some(LogLevel::Off),
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Off => "OFF",
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARN",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+}
+
+lazy_static! {
+    static ref LOG_LEVEL: LogLevel = match std::env::var("OPENAI_LOG") {
+        Ok(value) => LogLevel::parse(&value).unwrap_or_else(|| {
+            eprintln!("OpenAI:WARN: OPENAI_LOG was set to {value:?}, expected one of off, error, warn, info or debug");
+            LogLevel::Off
+        }),
+        Err(_) => LogLevel::Off,
+    };
+}
+
+/// Logs `message` to stderr when `OPENAI_LOG` allows `level` and, with the `tracing`
+/// feature, as a `tracing` event of the `open_ai` target.
+///
+/// The message is only built when it is going to be written somewhere.
+pub(crate) fn log(level: LogLevel, message: impl FnOnce() -> String) {
+    let to_stderr = level != LogLevel::Off && level <= *LOG_LEVEL;
+
+    #[cfg(feature = "tracing")]
+    let to_tracing = match level {
+        LogLevel::Off => false,
+        LogLevel::Error => tracing::enabled!(target: "open_ai", tracing::Level::ERROR),
+        LogLevel::Warn => tracing::enabled!(target: "open_ai", tracing::Level::WARN),
+        LogLevel::Info => tracing::enabled!(target: "open_ai", tracing::Level::INFO),
+        LogLevel::Debug => tracing::enabled!(target: "open_ai", tracing::Level::DEBUG),
+    };
+    #[cfg(not(feature = "tracing"))]
+    let to_tracing = false;
+
+    if !to_stderr && !to_tracing {
+        return;
+    }
+
+    let message = message();
+    if to_stderr {
+        eprintln!("OpenAI:{}: {message}", level.name());
+    }
+
+    #[cfg(feature = "tracing")]
+    if to_tracing {
+        match level {
+            LogLevel::Off => {}
+            LogLevel::Error => tracing::error!(target: "open_ai", "{message}"),
+            LogLevel::Warn => tracing::warn!(target: "open_ai", "{message}"),
+            LogLevel::Info => tracing::info!(target: "open_ai", "{message}"),
+            LogLevel::Debug => tracing::debug!(target: "open_ai", "{message}"),
+        }
+    }
+}
+
+/// Formats headers for the logs, hiding the credentials.
+pub(crate) fn redacted_headers(headers: &HeaderMap) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if is_secret(name) {
+                "***"
+            } else {
+                value.to_str().unwrap_or("<binary>")
+            };
+            format!("{name}: {value}")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn is_secret(name: &HeaderName) -> bool {
+    matches!(name.as_str(), "authorization" | "proxy-authorization" | "api-key" | "x-api-key")
+}
+
+/// The `tracing` span covering an API call, from its first attempt to its parsed response
+/// or the end of its stream. Without the `tracing` feature it records nothing.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl RequestSpan {
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn new<Req: Default + Clone + Serialize>(opts: &FinalRequestOptions<Req>) -> Self {
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::info_span!(
+                target: "open_ai",
+                "openai.request",
+                method = %opts.method,
+                path = %opts.path,
+                model = tracing::field::Empty,
+                retry_count = 0u32,
+                status = tracing::field::Empty,
+                request_id = tracing::field::Empty,
+                prompt_tokens = tracing::field::Empty,
+                completion_tokens = tracing::field::Empty,
+                total_tokens = tracing::field::Empty,
+            );
+
+            if !span.is_disabled() {
+                let model = opts.body.as_ref()
+                    .and_then(|body| serde_json::to_value(body).ok())
+                    .and_then(|body| body.get("model")?.as_str().map(str::to_string));
+                if let Some(model) = model {
+                    span.record("model", model);
+                }
+            }
+
+            RequestSpan { span }
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        RequestSpan {}
+    }
+
+    /// Runs `future` inside the span.
+    pub(crate) fn instrument<F: Future>(&self, future: F) -> impl Future<Output=F::Output> {
+        #[cfg(feature = "tracing")]
+        {
+            tracing::Instrument::instrument(future, self.span.clone())
+        }
+
+        #[cfg(not(feature = "tracing"))]
+        future
+    }
+
+    /// Records the token usage found in a response body or a stream event, like the `usage`
+    /// of a `ChatCompletion`, of the last `ChatCompletionChunk` or of a `Run`.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    pub(crate) fn record_usage(&self, body: &str) {
+        #[cfg(feature = "tracing")]
+        {
+            if self.span.is_disabled() || !body.contains("\"usage\"") {
+                return;
+            }
+            let Ok(body) = serde_json::from_str::<serde_json::Value>(body) else {
+                return;
+            };
+            for field in ["prompt_tokens", "completion_tokens", "total_tokens"] {
+                if let Some(tokens) = body["usage"][field].as_u64() {
+                    self.span.record(field, tokens);
+                }
+            }
+        }
+    }
+}
+
+/// Records the attempt number on the span of the current request.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn record_attempt(retry_count: u32) {
+    #[cfg(feature = "tracing")]
+    tracing::Span::current().record("retry_count", retry_count);
+}
+
+/// Records the status and the request id of a response on the span of the current request.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn record_response(response: &Response) {
+    #[cfg(feature = "tracing")]
+    {
+        let span = tracing::Span::current();
+        span.record("status", response.status().as_u16());
+        if let Some(request_id) = response.headers().get("x-request-id").and_then(|value| value.to_str().ok()) {
+            span.record("request_id", request_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_log_levels() {
+        assert_eq!(LogLevel::parse("debug"), Some(LogLevel::Debug));
+        assert_eq!(LogLevel::parse(" INFO "), Some(LogLevel::Info));
+        assert_eq!(LogLevel::parse(""), Some(LogLevel::Off));
+        assert_eq!(LogLevel::parse("verbose"), None);
+        assert!(LogLevel::Info < LogLevel::Debug);
+    }
+
+    #[test]
+    fn redacts_credentials() {
+        let mut headers = HeaderMap::new();
+        headers.insert("Authorization", "Bearer sk-secret".parse().unwrap());
+        headers.insert("OpenAI-Organization", "org-123".parse().unwrap());
+
+        let logged = redacted_headers(&headers);
+
+        assert_eq!(logged, "authorization: ***, openai-organization: org-123");
+    }
+}
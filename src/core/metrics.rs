@@ -0,0 +1,534 @@
+use futures::Stream;
+#[cfg(feature = "opentelemetry")]
+use std::collections::VecDeque;
+#[cfg(feature = "opentelemetry")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "opentelemetry")]
+use std::time::Instant;
+#[cfg(feature = "opentelemetry")]
+use opentelemetry::metrics::{Histogram, Meter};
+#[cfg(feature = "opentelemetry")]
+use opentelemetry::KeyValue;
+#[cfg(feature = "opentelemetry")]
+use reqwest::Method;
+use serde::Serialize;
+use crate::core::FinalRequestOptions;
+use crate::error::Error;
+
+/// Records the [OpenTelemetry GenAI client metrics](https://opentelemetry.io/docs/specs/semconv/gen-ai/gen-ai-metrics/)
+/// of the chat completions, the legacy completions and the assistant runs.
+///
+/// - `gen_ai.client.operation.duration`: the duration of each call, in seconds. Failed calls carry
+///   an `error.type` attribute, so the count of its data points gives the errors by type.
+/// - `gen_ai.client.token.usage`: the input and output tokens of each call, by `gen_ai.token.type`.
+/// - `gen_ai.client.operation.time_to_first_chunk`: the time until the first event of a stream,
+///   in seconds. This is the client side of `gen_ai.server.time_to_first_token`.
+///
+/// Every data point is labelled with `gen_ai.operation.name`, `gen_ai.request.model`,
+/// `gen_ai.response.model`, `server.address` and the `url.template` of the endpoint.
+///
+/// The client records them with the meter of the global `MeterProvider`, unless
+/// `ClientOptions::meter` is set. Without the `opentelemetry` feature nothing is recorded.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    #[cfg(feature = "opentelemetry")]
+    instruments: Option<Arc<Instruments>>,
+}
+
+#[cfg(feature = "opentelemetry")]
+#[derive(Debug)]
+struct Instruments {
+    duration: Histogram<f64>,
+    token_usage: Histogram<u64>,
+    time_to_first_chunk: Histogram<f64>,
+    /// The ids of the last runs whose usage was recorded, oldest first.
+    recorded_runs: Mutex<VecDeque<String>>,
+}
+
+/// How many run ids are remembered to record the usage of each run once.
+#[cfg(feature = "opentelemetry")]
+const RECORDED_RUNS_CAPACITY: usize = 1024;
+
+#[cfg(feature = "opentelemetry")]
+impl Instruments {
+    /// Whether the usage of `run` is still to be recorded, remembering that it is now.
+    ///
+    /// A run can end a stream and be retrieved by any number of polls afterwards, while
+    /// its usage must only be counted once.
+    fn claim_run_usage(&self, run: &serde_json::Value) -> bool {
+        let Some(id) = run["id"].as_str() else {
+            return true;
+        };
+        let mut recorded_runs = self.recorded_runs.lock().unwrap();
+        if recorded_runs.iter().any(|recorded| recorded == id) {
+            return false;
+        }
+        if recorded_runs.len() == RECORDED_RUNS_CAPACITY {
+            recorded_runs.pop_front();
+        }
+        recorded_runs.push_back(id.to_string());
+        true
+    }
+}
+
+impl Metrics {
+    /// Creates the instruments with `meter`.
+    #[cfg(feature = "opentelemetry")]
+    pub fn new(meter: &Meter) -> Self {
+        const DURATION_BUCKETS: [f64; 14] = [
+            0.01, 0.02, 0.04, 0.08, 0.16, 0.32, 0.64, 1.28, 2.56, 5.12, 10.24, 20.48, 40.96, 81.92,
+        ];
+        const TOKEN_BUCKETS: [f64; 14] = [
+            1.0, 4.0, 16.0, 64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0,
+            4194304.0, 16777216.0, 67108864.0,
+        ];
+
+        let instruments = Instruments {
+            duration: meter.f64_histogram("gen_ai.client.operation.duration")
+                .with_description("GenAI operation duration.")
+                .with_unit("s")
+                .with_boundaries(DURATION_BUCKETS.to_vec())
+                .build(),
+            token_usage: meter.u64_histogram("gen_ai.client.token.usage")
+                .with_description("Number of input and output tokens used.")
+                .with_unit("{token}")
+                .with_boundaries(TOKEN_BUCKETS.to_vec())
+                .build(),
+            time_to_first_chunk: meter.f64_histogram("gen_ai.client.operation.time_to_first_chunk")
+                .with_description("Time to receive the first chunk of a streamed response.")
+                .with_unit("s")
+                .with_boundaries(DURATION_BUCKETS.to_vec())
+                .build(),
+            recorded_runs: Mutex::new(VecDeque::new()),
+        };
+
+        Metrics { instruments: Some(Arc::new(instruments)) }
+    }
+
+    /// Starts recording the call described by `opts`, if it is one of the measured operations.
+    #[cfg_attr(not(feature = "opentelemetry"), allow(unused_variables))]
+    pub(crate) fn start<Req: Default + Clone + Serialize>(&self, base_url: &str, opts: &FinalRequestOptions<Req>) -> RequestMetrics {
+        #[cfg(feature = "opentelemetry")]
+        {
+            if self.instruments.is_none() {
+                return RequestMetrics::default();
+            }
+            let Some((operation, template)) = operation(&opts.method, &opts.path) else {
+                return RequestMetrics::default();
+            };
+            let body = opts.body.as_ref().and_then(|body| serde_json::to_value(body).ok());
+            self.recording(base_url, operation, template, body.as_ref())
+        }
+
+        #[cfg(not(feature = "opentelemetry"))]
+        RequestMetrics::default()
+    }
+
+    #[cfg(feature = "opentelemetry")]
+    fn recording(&self, base_url: &str, operation: &'static str, template: &'static str, body: Option<&serde_json::Value>) -> RequestMetrics {
+        let Some(instruments) = &self.instruments else {
+            return RequestMetrics::default();
+        };
+
+        let mut attributes = vec![
+            KeyValue::new("gen_ai.operation.name", operation),
+            KeyValue::new("gen_ai.provider.name", "openai"),
+            KeyValue::new("url.template", template),
+        ];
+        if let Some(model) = body.and_then(|body| body["model"].as_str()) {
+            attributes.push(KeyValue::new("gen_ai.request.model", model.to_string()));
+        }
+        if let Ok(url) = reqwest::Url::parse(base_url) {
+            if let Some(host) = url.host_str() {
+                attributes.push(KeyValue::new("server.address", host.to_string()));
+            }
+            if let Some(port) = url.port_or_known_default() {
+                attributes.push(KeyValue::new("server.port", port as i64));
+            }
+        }
+
+        RequestMetrics {
+            recording: Some(Arc::new(Mutex::new(Recording {
+                instruments: instruments.clone(),
+                attributes,
+                started_at: Instant::now(),
+                received_first_chunk: false,
+                finished: false,
+            }))),
+        }
+    }
+
+    /// Records the token usage of a run that was polled to completion, as polling
+    /// retrieves are not measured themselves. The usage of a run is only recorded once,
+    /// however many times it is polled or streamed to its end.
+    #[cfg_attr(not(feature = "opentelemetry"), allow(unused_variables))]
+    pub(crate) fn record_run_usage(&self, base_url: &str, run: &serde_json::Value) {
+        #[cfg(feature = "opentelemetry")]
+        {
+            let recording = self.recording(base_url, "invoke_agent", "/threads/{thread_id}/runs/{run_id}", Some(run));
+            if let Some(recording) = &recording.recording {
+                let mut recording = recording.lock().unwrap();
+                // The duration of a polled run isn't a single call, so it's not recorded.
+                recording.finished = true;
+                if recording.instruments.claim_run_usage(run) {
+                    recording.record_usage(run);
+                }
+            }
+        }
+    }
+}
+
+/// Maps the measured endpoints to their GenAI operation name and URL template.
+#[cfg(feature = "opentelemetry")]
+fn operation(method: &Method, path: &str) -> Option<(&'static str, &'static str)> {
+    if method != Method::POST {
+        return None;
+    }
+
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["chat", "completions"] => Some(("chat", "/chat/completions")),
+        ["completions"] => Some(("text_completion", "/completions")),
+        ["threads", "runs"] => Some(("invoke_agent", "/threads/runs")),
+        ["threads", _, "runs"] => Some(("invoke_agent", "/threads/{thread_id}/runs")),
+        ["threads", _, "runs", _, "submit_tool_outputs"] => {
+            Some(("invoke_agent", "/threads/{thread_id}/runs/{run_id}/submit_tool_outputs"))
+        }
+        _ => None,
+    }
+}
+
+/// Whether the stream event named `event` carries the total usage of the call.
+#[cfg(feature = "opentelemetry")]
+fn carries_total_usage(event: &str) -> bool {
+    !event.starts_with("thread.") || matches!(
+        event,
+        "thread.run.completed" | "thread.run.incomplete" | "thread.run.failed" | "thread.run.cancelled" | "thread.run.expired"
+    )
+}
+
+/// The metrics of a single call, finished once with its outcome.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RequestMetrics {
+    #[cfg(feature = "opentelemetry")]
+    recording: Option<Arc<Mutex<Recording>>>,
+}
+
+#[cfg(feature = "opentelemetry")]
+#[derive(Debug)]
+struct Recording {
+    instruments: Arc<Instruments>,
+    attributes: Vec<KeyValue>,
+    started_at: Instant,
+    received_first_chunk: bool,
+    finished: bool,
+}
+
+#[cfg(feature = "opentelemetry")]
+impl Recording {
+    /// Takes the response model and records the `usage` of a response body or of a stream event.
+    fn record_usage(&mut self, body: &serde_json::Value) {
+        if let Some(model) = body["model"].as_str() {
+            self.attributes.retain(|attribute| attribute.key.as_str() != "gen_ai.response.model");
+            self.attributes.push(KeyValue::new("gen_ai.response.model", model.to_string()));
+        }
+
+        let usage = &body["usage"];
+        for (field, token_type) in [("prompt_tokens", "input"), ("completion_tokens", "output")] {
+            if let Some(tokens) = usage[field].as_u64() {
+                let mut attributes = self.attributes.clone();
+                attributes.push(KeyValue::new("gen_ai.token.type", token_type));
+                self.instruments.token_usage.record(tokens, &attributes);
+            }
+        }
+    }
+}
+
+impl RequestMetrics {
+    /// Records the response model and token usage of a response body.
+    #[cfg_attr(not(feature = "opentelemetry"), allow(unused_variables))]
+    pub(crate) fn record_body(&self, body: &str) {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(recording) = &self.recording {
+            if let Ok(body) = serde_json::from_str::<serde_json::Value>(body) {
+                recording.lock().unwrap().record_usage(&body);
+            }
+        }
+    }
+
+    /// Records the time to the first chunk of a stream and the usage sent by its events.
+    ///
+    /// Only the chunks with the total usage of the call are counted: the last
+    /// `ChatCompletionChunk`, or the event ending a run, like `thread.run.completed`. The
+    /// usage of the steps of a run is already part of the usage of the run.
+    #[cfg_attr(not(feature = "opentelemetry"), allow(unused_variables))]
+    pub(crate) fn record_chunk(&self, event: &str, data: &str) {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(recording) = &self.recording {
+            let mut recording = recording.lock().unwrap();
+            if !recording.received_first_chunk {
+                recording.received_first_chunk = true;
+                let elapsed = recording.started_at.elapsed().as_secs_f64();
+                recording.instruments.time_to_first_chunk.record(elapsed, &recording.attributes);
+            }
+            if carries_total_usage(event) && data.contains("\"usage\"") {
+                if let Ok(data) = serde_json::from_str::<serde_json::Value>(data) {
+                    let is_run = data["object"] == "thread.run";
+                    if data["usage"].is_object() && (!is_run || recording.instruments.claim_run_usage(&data)) {
+                        recording.record_usage(&data);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the duration of the call, once, with the type of the error it failed with.
+    #[cfg_attr(not(feature = "opentelemetry"), allow(unused_variables))]
+    pub(crate) fn finish(&self, error: Option<&Error>) {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(recording) = &self.recording {
+            let mut recording = recording.lock().unwrap();
+            if recording.finished {
+                return;
+            }
+            recording.finished = true;
+
+            let mut attributes = recording.attributes.clone();
+            if let Some(error) = error {
+                attributes.push(KeyValue::new("error.type", error_type(error)));
+            }
+            let elapsed = recording.started_at.elapsed().as_secs_f64();
+            recording.instruments.duration.record(elapsed, &attributes);
+        }
+    }
+
+    /// Finishes the metrics when `stream` ends, fails or is dropped.
+    pub(crate) fn observe<T>(
+        self,
+        stream: impl Stream<Item=Result<T, Error>>,
+    ) -> impl Stream<Item=Result<T, Error>> {
+        #[cfg(feature = "opentelemetry")]
+        {
+            use futures::StreamExt;
+
+            /// Finishes the metrics of a stream dropped before its end.
+            struct FinishOnDrop(RequestMetrics);
+
+            impl Drop for FinishOnDrop {
+                fn drop(&mut self) {
+                    self.0.finish(None);
+                }
+            }
+
+            futures::stream::unfold(
+                (Box::pin(stream), FinishOnDrop(self)),
+                |(mut stream, guard)| async move {
+                    let item = stream.next().await;
+                    match &item {
+                        None => guard.0.finish(None),
+                        Some(Err(error)) => guard.0.finish(Some(error)),
+                        Some(Ok(_)) => {}
+                    }
+                    item.map(|item| (item, (stream, guard)))
+                },
+            )
+        }
+
+        #[cfg(not(feature = "opentelemetry"))]
+        stream
+    }
+}
+
+/// The `error.type` of a failed call: the status code of API errors, a short name otherwise.
+#[cfg(feature = "opentelemetry")]
+fn error_type(error: &Error) -> String {
+    if let Some(status) = error.status() {
+        return status.as_u16().to_string();
+    }
+    match error {
+        Error::Connection { .. } => "connection_error",
+        Error::ConnectionTimeout { .. } => "timeout",
+        Error::StreamIdleTimeout(_) => "stream_idle_timeout",
+        Error::UserAbort => "cancelled",
+        Error::Deserialize(_) => "invalid_response",
+        _ => "_OTHER",
+    }.to_string()
+}
+
+#[cfg(all(test, feature = "opentelemetry"))]
+mod tests {
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, HistogramDataPoint, MetricData};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, PeriodicReader, SdkMeterProvider};
+    use futures::TryStreamExt;
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::core::{APIClient, InMemoryTransport, RequestOptions};
+    use crate::resources::beta::threads::runs::runs::Runs;
+    use crate::test_support::run_json;
+
+    fn client_with_metrics(transport: &Arc<InMemoryTransport>) -> (APIClient, SdkMeterProvider, InMemoryMetricExporter) {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+
+        let mut client = APIClient::with_transport(transport);
+        client.base_url = "https://api.openai.com/v1".to_string();
+        client.metrics = Metrics::new(&provider.meter("open_ai"));
+        (client, provider, exporter)
+    }
+
+    /// The sorted attributes, count and sum of a histogram data point.
+    type Point = (Vec<(String, String)>, u64, f64);
+
+    fn histogram_points(exporter: &InMemoryMetricExporter, name: &str) -> Vec<Point> {
+        let mut points = Vec::new();
+        for resource_metrics in exporter.get_finished_metrics().unwrap() {
+            for metric in resource_metrics.scope_metrics().flat_map(|scope| scope.metrics()) {
+                if metric.name() != name {
+                    continue;
+                }
+                let attributes = |point_attributes: &mut dyn Iterator<Item=&KeyValue>| {
+                    let mut attributes: Vec<(String, String)> = point_attributes
+                        .map(|attribute| (attribute.key.to_string(), attribute.value.to_string()))
+                        .collect();
+                    attributes.sort();
+                    attributes
+                };
+                match metric.data() {
+                    AggregatedMetrics::F64(MetricData::Histogram(histogram)) => {
+                        points.extend(histogram.data_points().map(|point: &HistogramDataPoint<f64>| {
+                            (attributes(&mut point.attributes()), point.count(), point.sum())
+                        }));
+                    }
+                    AggregatedMetrics::U64(MetricData::Histogram(histogram)) => {
+                        points.extend(histogram.data_points().map(|point: &HistogramDataPoint<u64>| {
+                            (attributes(&mut point.attributes()), point.count(), point.sum() as f64)
+                        }));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        points
+    }
+
+    #[tokio::test]
+    async fn records_chat_completion_duration_and_usage() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&json!({
+            "id": "chatcmpl-123",
+            "model": "gpt-4o-2024-05-13",
+            "usage": {"prompt_tokens": 12, "completion_tokens": 30, "total_tokens": 42},
+        }));
+        let (client, provider, exporter) = client_with_metrics(&transport);
+
+        client.post::<Value, Value, ()>("/chat/completions", Some(RequestOptions {
+            body: Some(json!({"model": "gpt-4o", "messages": []})),
+            ..Default::default()
+        })).await.unwrap();
+        provider.force_flush().unwrap();
+
+        let durations = histogram_points(&exporter, "gen_ai.client.operation.duration");
+        assert_eq!(durations.len(), 1);
+        let (attributes, count, _) = &durations[0];
+        assert_eq!(*count, 1);
+        assert!(attributes.contains(&("gen_ai.operation.name".to_string(), "chat".to_string())));
+        assert!(attributes.contains(&("gen_ai.request.model".to_string(), "gpt-4o".to_string())));
+        assert!(attributes.contains(&("gen_ai.response.model".to_string(), "gpt-4o-2024-05-13".to_string())));
+        assert!(attributes.contains(&("server.address".to_string(), "api.openai.com".to_string())));
+
+        let mut tokens: Vec<(String, f64)> = histogram_points(&exporter, "gen_ai.client.token.usage")
+            .into_iter()
+            .map(|(attributes, _, sum)| {
+                let token_type = attributes.into_iter().find(|(key, _)| key == "gen_ai.token.type").unwrap().1;
+                (token_type, sum)
+            })
+            .collect();
+        tokens.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(tokens, [("input".to_string(), 12.0), ("output".to_string(), 30.0)]);
+    }
+
+    #[tokio::test]
+    async fn records_the_usage_of_assistant_streams_once() {
+        let usage = json!({"prompt_tokens": 20, "completion_tokens": 8, "total_tokens": 28});
+        let events = [
+            ("thread.run.created", json!({"id": "run_abc", "object": "thread.run", "status": "queued", "usage": null})),
+            ("thread.run.step.completed", json!({"id": "step_abc", "object": "thread.run.step", "usage": usage})),
+            ("thread.run.completed", json!({"id": "run_abc", "object": "thread.run", "status": "completed", "usage": usage})),
+        ];
+        let body: String = events.iter().map(|(event, data)| format!("event: {event}\ndata: {data}\n\n")).collect();
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_response(200, &[("content-type", "text/event-stream")], body);
+        let (client, provider, exporter) = client_with_metrics(&transport);
+
+        let stream = client.post::<Value, Value, Value>("/threads/thread_abc/runs", Some(RequestOptions {
+            body: Some(json!({"assistant_id": "asst_abc", "stream": true})),
+            stream: Some(true),
+            ..Default::default()
+        })).into_stream();
+        let events: Vec<Value> = stream.try_collect().await.unwrap();
+        assert_eq!(events.len(), 3);
+        provider.force_flush().unwrap();
+
+        let tokens: Vec<(u64, f64)> = histogram_points(&exporter, "gen_ai.client.token.usage")
+            .into_iter()
+            .map(|(_, count, sum)| (count, sum))
+            .collect();
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens.contains(&(1, 20.0)));
+        assert!(tokens.contains(&(1, 8.0)));
+    }
+
+    #[tokio::test]
+    async fn records_the_usage_of_a_run_polled_twice_once() {
+        let mut run = run_json("completed");
+        run["usage"] = json!({"prompt_tokens": 20, "completion_tokens": 8, "total_tokens": 28});
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&run).push_json(&run);
+        let (client, provider, exporter) = client_with_metrics(&transport);
+        let runs = Runs { client: Some(Arc::new(Mutex::new(client))) };
+
+        for _ in 0..2 {
+            runs.poll("thread_abc", "run_abc", None).await.unwrap();
+        }
+        provider.force_flush().unwrap();
+
+        assert_eq!(transport.requests().len(), 2);
+        let tokens: Vec<(u64, f64)> = histogram_points(&exporter, "gen_ai.client.token.usage")
+            .into_iter()
+            .map(|(_, count, sum)| (count, sum))
+            .collect();
+        assert_eq!(tokens.len(), 2);
+        assert!(tokens.contains(&(1, 20.0)));
+        assert!(tokens.contains(&(1, 8.0)));
+    }
+
+    #[tokio::test]
+    async fn records_the_error_type_of_failed_calls() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_response(429, &[], r#"{"error": {"message": "Rate limit reached", "type": "requests"}}"#);
+        let (client, provider, exporter) = client_with_metrics(&transport);
+
+        let result = client.post::<Value, Value, ()>("/completions", Some(RequestOptions {
+            body: Some(json!({"model": "gpt-3.5-turbo-instruct", "prompt": "Say this is a test"})),
+            ..Default::default()
+        })).await;
+        assert!(result.is_err());
+        provider.force_flush().unwrap();
+
+        let durations = histogram_points(&exporter, "gen_ai.client.operation.duration");
+        assert_eq!(durations.len(), 1);
+        assert!(durations[0].0.contains(&("error.type".to_string(), "429".to_string())));
+        assert!(durations[0].0.contains(&("gen_ai.operation.name".to_string(), "text_completion".to_string())));
+    }
+
+    #[test]
+    fn only_measures_generation_endpoints() {
+        assert_eq!(operation(&Method::POST, "/threads/thread_abc/runs").unwrap().0, "invoke_agent");
+        assert_eq!(operation(&Method::POST, "/chat/completions").unwrap().0, "chat");
+        assert!(operation(&Method::GET, "/threads/thread_abc/runs/run_abc").is_none());
+        assert!(operation(&Method::POST, "/threads/thread_abc/messages").is_none());
+    }
+}
@@ -0,0 +1,38 @@
+use std::fmt::Debug;
+use std::time::Instant;
+use async_trait::async_trait;
+use reqwest::{Method, Request, Response, Url};
+use crate::error::Error;
+
+/// Describes the HTTP attempt a [`Middleware`] hook is called for.
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+    pub method: Method,
+    pub url: Url,
+    /// `0` for the first attempt, incremented on every retry.
+    pub retry_count: u32,
+    /// When the attempt was started, useful to record latencies.
+    pub started_at: Instant,
+}
+
+/// Hooks around every HTTP attempt sent by `APIClient`, including retries and the
+/// connections opened by `APIFuture::into_stream`.
+///
+/// Middlewares run in the order they are configured in `ClientOptions::middleware` for
+/// `before_request`, and in the reverse order for `after_response` and `on_error`.
+#[async_trait]
+pub trait Middleware: Debug + Send + Sync {
+    /// Called before the request is sent. Returning an error aborts the attempt.
+    async fn before_request(&self, _context: &RequestContext, _request: &mut Request) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called once the response headers are received, whatever the status code.
+    /// Returning an error fails the attempt.
+    async fn after_response(&self, _context: &RequestContext, _response: &mut Response) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called when the attempt failed without a response, e.g. on connection errors and timeouts.
+    async fn on_error(&self, _context: &RequestContext, _error: &Error) {}
+}
@@ -1,5 +1,17 @@
 mod core;
+mod logging;
+mod metrics;
+mod middleware;
+mod multipart;
+mod platform;
 mod request_options;
+mod response;
+mod transport;
 pub mod streaming;
 
-pub use core::*;
\ No newline at end of file
+pub use core::*;
+pub use response::*;
+pub use transport::*;
+pub use middleware::*;
+pub use multipart::*;
+pub use metrics::Metrics;
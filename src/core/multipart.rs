@@ -0,0 +1,340 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use bytes::{Bytes, BytesMut};
+use futures::future::{self, Either};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use reqwest::Body;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::fs::File;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use uuid::Uuid;
+use crate::core::core::stringify_query_value;
+use crate::error::Error;
+
+/// The size of the chunks files are streamed from disk in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A `multipart/form-data` request body, made of text fields and files.
+///
+/// Set it as the `multipart` request option; it replaces the JSON body of the request.
+/// The body is encoded again for every attempt of the request, so the files read from
+/// disk are reopened when the request is retried.
+#[derive(Debug, Clone)]
+pub struct MultipartForm {
+    boundary: String,
+    parts: Vec<(String, Part)>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    File(FilePart),
+}
+
+/// A file sent in a multipart form, with its name and MIME type.
+#[derive(Debug, Clone)]
+pub struct FilePart {
+    filename: String,
+    content_type: String,
+    source: FileSource,
+}
+
+#[derive(Debug, Clone)]
+enum FileSource {
+    Bytes(Bytes),
+    /// Streamed from disk when the request is sent.
+    Path(PathBuf),
+}
+
+/// A piece of an encoded form.
+enum Chunk {
+    Bytes(Bytes),
+    File(PathBuf),
+}
+
+impl Default for MultipartForm {
+    fn default() -> Self {
+        MultipartForm {
+            boundary: format!("----open_ai-{}", Uuid::new_v4().simple()),
+            parts: Vec::new(),
+        }
+    }
+}
+
+impl MultipartForm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A form with a text field for each field of `params`, e.g. the params of a request
+    /// whose files are skipped when serialized and added with [`MultipartForm::file`].
+    ///
+    /// `None` fields are left out, arrays and objects are sent as `name[]` and
+    /// `name[field]` fields like in the query of a request.
+    pub fn from_fields(params: &impl Serialize) -> Self {
+        let mut fields = Vec::new();
+        if let Ok(Value::Object(params)) = serde_json::to_value(params) {
+            for (name, value) in params {
+                stringify_query_value(&mut fields, name, value);
+            }
+        }
+        fields.into_iter().fold(MultipartForm::new(), |form, (name, value)| form.text(&name, value))
+    }
+
+    /// Adds a text field.
+    pub fn text(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.parts.push((name.to_string(), Part::Text(value.into())));
+        self
+    }
+
+    /// Adds a file field.
+    pub fn file(mut self, name: &str, file: FilePart) -> Self {
+        self.parts.push((name.to_string(), Part::File(file)));
+        self
+    }
+
+    /// The `Content-Type` header of the request, with the boundary of the form.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Encodes the form as the body of a request.
+    ///
+    /// Forms without files read from disk are sent as a single buffer, the others are
+    /// streamed, opening their files once the body is polled.
+    pub fn body(&self) -> Body {
+        if self.parts.iter().any(|(_, part)| matches!(part, Part::File(FilePart { source: FileSource::Path(_), .. }))) {
+            Body::wrap_stream(self.stream())
+        } else {
+            Body::from(self.to_bytes())
+        }
+    }
+
+    /// Opens the files of the form read from disk, failing with the path of the first one
+    /// that can't be read.
+    pub(crate) async fn check_files(&self) -> Result<(), Error> {
+        for (_, part) in &self.parts {
+            if let Part::File(FilePart { source: FileSource::Path(path), .. }) = part {
+                File::open(path).await
+                    .map_err(|error| Error::OpenAI(format!("Failed to read {}: {error}", path.display())))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes the form, reading the files from disk in chunks.
+    pub fn stream(&self) -> impl Stream<Item=io::Result<Bytes>> + Send + Sync + 'static {
+        let mut chunks = Vec::new();
+        for (name, part) in &self.parts {
+            match part {
+                Part::Text(value) => {
+                    let mut header = self.part_header(name, None);
+                    header.extend_from_slice(value.as_bytes());
+                    header.extend_from_slice(b"\r\n");
+                    chunks.push(Chunk::Bytes(header.freeze()));
+                }
+                Part::File(file) => {
+                    chunks.push(Chunk::Bytes(self.part_header(name, Some(file)).freeze()));
+                    chunks.push(match &file.source {
+                        FileSource::Bytes(data) => Chunk::Bytes(data.clone()),
+                        FileSource::Path(path) => Chunk::File(path.clone()),
+                    });
+                    chunks.push(Chunk::Bytes(Bytes::from_static(b"\r\n")));
+                }
+            }
+        }
+        chunks.push(Chunk::Bytes(Bytes::from(format!("--{}--\r\n", self.boundary))));
+
+        stream::iter(chunks).flat_map(|chunk| match chunk {
+            Chunk::Bytes(bytes) => Either::Left(stream::once(future::ready(Ok(bytes)))),
+            Chunk::File(path) => Either::Right(read_file(path)),
+        })
+    }
+
+    /// Encodes a form whose files are all in memory.
+    fn to_bytes(&self) -> Bytes {
+        let mut body = BytesMut::new();
+        for (name, part) in &self.parts {
+            match part {
+                Part::Text(value) => {
+                    body.extend_from_slice(&self.part_header(name, None));
+                    body.extend_from_slice(value.as_bytes());
+                }
+                Part::File(file) => {
+                    body.extend_from_slice(&self.part_header(name, Some(file)));
+                    if let FileSource::Bytes(data) = &file.source {
+                        body.extend_from_slice(data);
+                    }
+                }
+            }
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", self.boundary).as_bytes());
+        body.freeze()
+    }
+
+    /// The boundary and headers of a part, up to its content.
+    fn part_header(&self, name: &str, file: Option<&FilePart>) -> BytesMut {
+        let header = match file {
+            None => format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", self.boundary, escape(name)),
+            Some(file) => format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                self.boundary,
+                escape(name),
+                escape(&file.filename),
+                file.content_type,
+            ),
+        };
+        BytesMut::from(header.as_bytes())
+    }
+}
+
+impl FilePart {
+    /// A file with the given content, sent as `application/octet-stream`.
+    pub fn from_bytes(data: impl Into<Bytes>, filename: &str) -> Self {
+        FilePart {
+            filename: filename.to_string(),
+            content_type: "application/octet-stream".to_string(),
+            source: FileSource::Bytes(data.into()),
+        }
+    }
+
+    /// The file at `path`, named after its last component, with the MIME type of its
+    /// extension.
+    ///
+    /// The file is not loaded in memory: it is streamed from disk by every attempt of the
+    /// request.
+    pub async fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let metadata = tokio::fs::metadata(path).await
+            .map_err(|error| Error::OpenAI(format!("Failed to read {}: {error}", path.display())))?;
+        if !metadata.is_file() {
+            return Err(Error::OpenAI(format!("Failed to read {}: not a file", path.display())));
+        }
+
+        Ok(FilePart {
+            filename: path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+            content_type: mime_type(path).to_string(),
+            source: FileSource::Path(path.to_path_buf()),
+        })
+    }
+
+    /// Reads `reader` to its end.
+    ///
+    /// The content is kept in memory, so the request can be retried.
+    pub async fn from_reader(mut reader: impl AsyncRead + Unpin, filename: &str) -> Result<Self, Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).await
+            .map_err(|error| Error::OpenAI(format!("Failed to read {filename}: {error}")))?;
+        Ok(FilePart::from_bytes(data, filename))
+    }
+
+    /// Sets the MIME type of the file.
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type = content_type.to_string();
+        self
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+}
+
+/// Streams the file at `path`, opening it once polled.
+fn read_file(path: PathBuf) -> impl Stream<Item=io::Result<Bytes>> + Send + Sync + 'static {
+    stream::once(File::open(path))
+        .map_ok(|file| stream::try_unfold(file, |mut file| async move {
+            let mut chunk = BytesMut::with_capacity(CHUNK_SIZE);
+            match file.read_buf(&mut chunk).await? {
+                0 => Ok(None),
+                _ => Ok(Some((chunk.freeze(), file))),
+            }
+        }))
+        .try_flatten()
+}
+
+/// The MIME type of a file, from its extension.
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or_default();
+    match extension.to_ascii_lowercase().as_str() {
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "jsonl" => "application/jsonl",
+        "pdf" => "application/pdf",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "zip" => "application/zip",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "mp3" | "mpga" | "mpeg" => "audio/mpeg",
+        "m4a" => "audio/mp4",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "flac" => "audio/flac",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Escapes the quotes and line breaks of a name in a `Content-Disposition` header.
+fn escape(name: &str) -> String {
+    name.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct TranscriptionParams {
+        #[serde(skip)]
+        file: Option<FilePart>,
+        model: String,
+        temperature: Option<f32>,
+        timestamp_granularities: Vec<String>,
+    }
+
+    #[tokio::test]
+    async fn streams_typed_fields_and_files_from_disk() {
+        let path = std::env::temp_dir().join(format!("open_ai-{}.mp3", Uuid::new_v4()));
+        tokio::fs::write(&path, vec![7u8; CHUNK_SIZE + 10]).await.unwrap();
+        let params = TranscriptionParams {
+            file: Some(FilePart::from_path(&path).await.unwrap()),
+            model: "whisper-1".to_string(),
+            temperature: None,
+            timestamp_granularities: vec!["word".to_string(), "segment".to_string()],
+        };
+
+        let form = MultipartForm::from_fields(&params).file("file", params.file.clone().unwrap());
+        // Every attempt reads the file again.
+        for _ in 0..2 {
+            let chunks: Vec<Bytes> = form.stream().try_collect().await.unwrap();
+            let body = chunks.concat();
+            let boundary = &form.boundary;
+            let filename = path.file_name().unwrap().to_str().unwrap();
+            let expected = [
+                format!("--{boundary}\r\nContent-Disposition: form-data; name=\"model\"\r\n\r\nwhisper-1\r\n").into_bytes(),
+                format!("--{boundary}\r\nContent-Disposition: form-data; name=\"timestamp_granularities[]\"\r\n\r\nword\r\n").into_bytes(),
+                format!("--{boundary}\r\nContent-Disposition: form-data; name=\"timestamp_granularities[]\"\r\n\r\nsegment\r\n").into_bytes(),
+                format!("--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{filename}\"\r\nContent-Type: audio/mpeg\r\n\r\n").into_bytes(),
+                vec![7u8; CHUNK_SIZE + 10],
+                format!("\r\n--{boundary}--\r\n").into_bytes(),
+            ].concat();
+            assert_eq!(body, expected);
+        }
+
+        tokio::fs::remove_file(&path).await.unwrap();
+        let error = form.stream().try_collect::<Vec<Bytes>>().await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use lazy_static::lazy_static;
+use crate::core::Headers;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+lazy_static! {
+    static ref PLATFORM_HEADERS: Headers = {
+        let mut headers = HashMap::new();
+        headers.insert("X-Stainless-Lang".to_string(), Some("rust".to_string()));
+        headers.insert("X-Stainless-Package-Version".to_string(), Some(VERSION.to_string()));
+        headers.insert("X-Stainless-OS".to_string(), Some(normalize_platform(std::env::consts::OS)));
+        headers.insert("X-Stainless-Arch".to_string(), Some(normalize_arch(std::env::consts::ARCH)));
+        headers.insert("X-Stainless-Runtime".to_string(), Some("rustc".to_string()));
+        headers.insert("X-Stainless-Runtime-Version".to_string(), Some(env!("OPEN_AI_RUSTC_VERSION").to_string()));
+        headers
+    };
+}
+
+pub fn user_agent() -> String {
+    format!("open_ai/{VERSION}")
+}
+
+/// The `X-Stainless-*` headers describing the platform the client runs on.
+pub fn platform_headers() -> Headers {
+    PLATFORM_HEADERS.clone()
+}
+
+fn normalize_platform(platform: &str) -> String {
+    match platform {
+        "macos" => "MacOS".to_string(),
+        "linux" => "Linux".to_string(),
+        "windows" => "Windows".to_string(),
+        "freebsd" => "FreeBSD".to_string(),
+        "openbsd" => "OpenBSD".to_string(),
+        "ios" => "iOS".to_string(),
+        "android" => "Android".to_string(),
+        platform => format!("Other:{platform}"),
+    }
+}
+
+fn normalize_arch(arch: &str) -> String {
+    match arch {
+        "x86" => "x32".to_string(),
+        "x86_64" => "x64".to_string(),
+        "arm" => "arm".to_string(),
+        "aarch64" => "arm64".to_string(),
+        arch => format!("other:{arch}"),
+    }
+}
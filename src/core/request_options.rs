@@ -3,6 +3,8 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use reqwest::{Client, Method, Request};
 use crate::core::core::Headers;
+use crate::core::multipart::MultipartForm;
+pub use tokio_util::sync::CancellationToken;
 
 #[derive(Default, Debug, Clone)]
 pub struct RequestOptions<Req: Default = ()> {
@@ -14,12 +16,17 @@ pub struct RequestOptions<Req: Default = ()> {
     pub max_retries: Option<u32>,
     pub stream: Option<bool>,
     pub timeout: Option<Duration>,
+    pub stream_idle_timeout: Option<Duration>,
     pub http_agent: Option<Arc<Mutex<Client>>>,
-    pub signal: Option<Arc<Mutex<tokio::sync::Notify>>>,
+    pub signal: Option<CancellationToken>,
     pub idempotency_key: Option<String>,
     pub binary_request: Option<bool>,
     pub binary_response: Option<bool>,
+    /// Sent as the `multipart/form-data` body of the request, instead of `body`.
+    pub multipart: Option<MultipartForm>,
     pub poll_interval_ms: Option<u32>,
+    pub poll_timeout: Option<Duration>,
+    pub cancel_run_on_abort: Option<bool>,
     // pub stream_class: Option<Arc<Mutex<Stream>>>,
 }
 
@@ -41,12 +48,16 @@ impl<T> RequestOptions<T> where T: Default {
             max_retries: self.max_retries,
             stream: self.stream,
             timeout: self.timeout,
+            stream_idle_timeout: self.stream_idle_timeout,
             http_agent: self.http_agent,
             signal: self.signal,
             idempotency_key: self.idempotency_key,
             binary_request: self.binary_request,
             binary_response: self.binary_response,
+            multipart: self.multipart,
             poll_interval_ms: self.poll_interval_ms,
+            poll_timeout: self.poll_timeout,
+            cancel_run_on_abort: self.cancel_run_on_abort,
         }
     }
 }
@@ -61,12 +72,17 @@ pub struct FinalRequestOptions<Req> where Req: Default + Clone {
     pub max_retries: Option<u32>,
     pub stream: Option<bool>,
     pub timeout: Option<Duration>,
+    pub stream_idle_timeout: Option<Duration>,
     pub http_agent: Option<Arc<Mutex<Client>>>,
-    pub signal: Option<Arc<Mutex<tokio::sync::Notify>>>,
+    pub signal: Option<CancellationToken>,
     pub idempotency_key: Option<String>,
     pub binary_request: Option<bool>,
     pub binary_response: Option<bool>,
+    /// Sent as the `multipart/form-data` body of the request, instead of `body`.
+    pub multipart: Option<MultipartForm>,
     pub poll_interval_ms: Option<u32>,
+    pub poll_timeout: Option<Duration>,
+    pub cancel_run_on_abort: Option<bool>,
     // pub stream_class: Option<Arc<Mutex<Stream>>>,
 }
 
@@ -81,12 +97,16 @@ impl<Req> FinalRequestOptions<Req> where Req: Default + Clone {
             max_retries: opts.max_retries,
             stream: opts.stream,
             timeout: opts.timeout,
+            stream_idle_timeout: opts.stream_idle_timeout,
             http_agent: opts.http_agent.as_ref().map(|x| x.clone()),
             signal: opts.signal.as_ref().map(|x| x.clone()),
             idempotency_key: opts.idempotency_key.as_ref().map(|x| x.clone()),
             binary_request: opts.binary_request,
             binary_response: opts.binary_response,
+            multipart: opts.multipart,
             poll_interval_ms: opts.poll_interval_ms,
+            poll_timeout: opts.poll_timeout,
+            cancel_run_on_abort: opts.cancel_run_on_abort,
         }
     }
 }
@@ -102,12 +122,16 @@ impl <Req> From<FinalRequestOptions<Req>> for RequestOptions<Req> where Req: Def
             max_retries: options.max_retries,
             stream: options.stream,
             timeout: options.timeout,
+            stream_idle_timeout: options.stream_idle_timeout,
             http_agent: options.http_agent,
             signal: options.signal,
             idempotency_key: options.idempotency_key,
             binary_request: options.binary_request,
             binary_response: options.binary_response,
+            multipart: options.multipart,
             poll_interval_ms: options.poll_interval_ms,
+            poll_timeout: options.poll_timeout,
+            cancel_run_on_abort: options.cancel_run_on_abort,
         }
     }
 }
@@ -130,12 +154,16 @@ impl<T> FinalRequestOptions<T> where T: Default + Clone {
             max_retries: self.max_retries,
             stream: self.stream,
             timeout: self.timeout,
+            stream_idle_timeout: self.stream_idle_timeout,
             http_agent: self.http_agent,
             signal: self.signal,
             idempotency_key: self.idempotency_key,
             binary_request: self.binary_request,
             binary_response: self.binary_response,
+            multipart: self.multipart,
             poll_interval_ms: self.poll_interval_ms,
+            poll_timeout: self.poll_timeout,
+            cancel_run_on_abort: self.cancel_run_on_abort,
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+use std::time::Duration;
+use reqwest::header::HeaderMap;
+use reqwest::{Response, StatusCode};
+
+/// Metadata of an HTTP response, returned by `APIFuture::with_response`.
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The `x-request-id` header, useful when reporting issues to OpenAI.
+    pub request_id: Option<String>,
+    pub rate_limit: RateLimit,
+    /// The time it took to receive the response, including retries.
+    pub elapsed: Duration,
+}
+
+impl ResponseMeta {
+    pub fn new(response: &Response, elapsed: Duration) -> Self {
+        let headers = response.headers().clone();
+        ResponseMeta {
+            status: response.status(),
+            request_id: header(&headers, "x-request-id"),
+            rate_limit: RateLimit::from_headers(&headers),
+            headers,
+            elapsed,
+        }
+    }
+}
+
+/// The `x-ratelimit-*` headers of a response.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimit {
+    pub limit_requests: Option<u64>,
+    pub limit_tokens: Option<u64>,
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    /// The time until the request limit resets, e.g. `1s`.
+    pub reset_requests: Option<String>,
+    /// The time until the token limit resets, e.g. `6m0s`.
+    pub reset_tokens: Option<String>,
+}
+
+impl RateLimit {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let number = |name: &str| header(headers, name).and_then(|value| value.parse().ok());
+
+        RateLimit {
+            limit_requests: number("x-ratelimit-limit-requests"),
+            limit_tokens: number("x-ratelimit-limit-tokens"),
+            remaining_requests: number("x-ratelimit-remaining-requests"),
+            remaining_tokens: number("x-ratelimit-remaining-tokens"),
+            reset_requests: header(headers, "x-ratelimit-reset-requests"),
+            reset_tokens: header(headers, "x-ratelimit-reset-tokens"),
+        }
+    }
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name).and_then(|value| value.to_str().ok()).map(|value| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_request_id_and_rate_limit_headers() {
+        let response = Response::from(
+            http::Response::builder()
+                .header("x-request-id", "req_123")
+                .header("x-ratelimit-limit-requests", "500")
+                .header("x-ratelimit-remaining-tokens", "149984")
+                .header("x-ratelimit-reset-tokens", "6m0s")
+                .body("")
+                .unwrap(),
+        );
+
+        let meta = ResponseMeta::new(&response, Duration::from_millis(120));
+        assert_eq!(meta.status, StatusCode::OK);
+        assert_eq!(meta.request_id.as_deref(), Some("req_123"));
+        assert_eq!(meta.rate_limit, RateLimit {
+            limit_requests: Some(500),
+            remaining_tokens: Some(149984),
+            reset_tokens: Some("6m0s".to_string()),
+            ..Default::default()
+        });
+    }
+}
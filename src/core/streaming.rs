@@ -1,16 +1,23 @@
 use std::any::Any;
-use futures::stream::{Stream};
+use eventsource_stream::{Event, Eventsource};
+use futures::future;
+use futures::stream::{Stream, StreamExt, TryStreamExt};
+use reqwest::header::HeaderMap;
 use reqwest::{RequestBuilder, Response};
-use reqwest_eventsource::{Event, EventSource};
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use serde_json::Value;
+use crate::error::Error;
 use std::fmt::Debug;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll};
-use serde::de::StdError;
-use crate::core::{APIClient, FinalRequestOptions};
-use crate::library::assistant_stream::AssistantStream;
+use std::time::{Duration, Instant};
+use crate::core::logging::{self, LogLevel, RequestSpan};
+use crate::core::metrics::RequestMetrics;
+use crate::core::{APIClient, FinalRequestOptions, Headers, RequestOptions, ResponseMeta};
+use crate::error::APIError;
+use crate::{ErrorObject, ErrorResponse};
 use crate::resources::beta::threads::MessageDelta;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,9 +37,9 @@ pub struct Choice {
 
 pub enum APIFutureState<ItemNonStreaming: for<'de> Deserialize<'de> = ()> {
     Init,
-    RequestSent(Pin<Box<dyn Future<Output=Result<Response, Box<dyn Error>>>>>),
-    ResponseTextCompleted(Pin<Box<dyn Future<Output=Result<String, Box<dyn Error>>>>>),
-    ResponseReceived(Pin<Box<dyn Future<Output=Result<ItemNonStreaming, Box<dyn Error>>>>>),
+    RequestSent(Pin<Box<dyn Future<Output=Result<Response, Error>>>>),
+    ResponseTextCompleted(Pin<Box<dyn Future<Output=Result<String, Error>>>>),
+    ResponseReceived(Pin<Box<dyn Future<Output=Result<ItemNonStreaming, Error>>>>),
 }
 
 pub struct APIFuture<
@@ -45,6 +52,8 @@ pub struct APIFuture<
     pub state: APIFutureState<ItemNonStreaming>,
     pub streaming_state: Option<ItemStreaming>,
     pub request_options: FinalRequestOptions<Req>,
+    pub(crate) span: RequestSpan,
+    pub(crate) metrics: RequestMetrics,
 }
 
 impl<'a, Req, ItemNonStreaming, ItemStreaming> Future for APIFuture<Req, ItemNonStreaming, ItemStreaming>
@@ -53,45 +62,49 @@ where
     ItemNonStreaming: 'a + for<'de> Deserialize<'de> + 'static,
     ItemStreaming: 'a + for<'de> Deserialize<'de> + 'static,
 {
-    type Output = Result<ItemNonStreaming, Box<dyn Error>>;
+    type Output = Result<ItemNonStreaming, Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = unsafe { self.get_unchecked_mut() };
-        let client = this.client.client.clone();
+
+        let result = futures::ready!(this.poll_response(cx));
+        this.metrics.finish(result.as_ref().err());
+        Poll::Ready(result)
+    }
+}
+
+impl<Req, ItemNonStreaming, ItemStreaming> APIFuture<Req, ItemNonStreaming, ItemStreaming>
+where
+    Req: Default + Clone + Serialize,
+    ItemNonStreaming: for<'de> Deserialize<'de> + 'static,
+    ItemStreaming: for<'de> Deserialize<'de> + 'static,
+{
+    fn poll_response(&mut self, cx: &mut Context<'_>) -> Poll<Result<ItemNonStreaming, Error>> {
+        let this = self;
 
         loop {
             match &mut this.state {
                 APIFutureState::Init => {
-                    let request = this.request.as_ref().unwrap().try_clone().unwrap();
-
-                    loop {
-
-                        let request_builder = request;
-
-                        let request = request_builder.build().unwrap(); // ?;
-
-                        let future_client = client.clone();
-                        let future = Box::pin(async move {
-                            future_client.execute(request).await.map_err(|e| Box::new(e) as Box<dyn StdError>)
-                        });
-                        this.state = APIFutureState::RequestSent(future);
-                        break;
-                    }
-                    // endregion
+                    let future = this.send();
+                    this.state = APIFutureState::RequestSent(future);
                 }
 
                 APIFutureState::RequestSent(future) => {
                     let response = futures::ready!(future.as_mut().poll(cx))?;
+                    let signal = this.request_options.signal.clone();
 
-                    let future = Box::pin(async move {
-                        response.text().await.map_err(|e| Box::new(e) as Box<dyn StdError>)
-                    });
+                    let future = Box::pin(APIClient::abortable(signal, async move {
+                        response.text().await.map_err(Error::from)
+                    }));
                     this.state = APIFutureState::ResponseTextCompleted(future);
                 }
                 APIFutureState::ResponseTextCompleted(future) => {
                     let response = futures::ready!(future.as_mut().poll(cx))?;
 
                     let str = response;
+                    logging::log(LogLevel::Debug, || format!("response body: {str}"));
+                    this.span.record_usage(&str);
+                    this.metrics.record_body(&str);
                     let parsed_response: ItemNonStreaming = serde_json::from_str(&str)?;
 
                     this.state = APIFutureState::ResponseReceived(Box::pin(async { Ok(parsed_response) }));
@@ -105,74 +118,246 @@ where
     }
 }
 
-impl<'a, Req: Default + Clone + Serialize, ItemNonStreaming: for<'de> Deserialize<'de> + Debug, ItemStreaming: for<'de> Deserialize<'de> + Debug + Any>
+impl<Req, ItemNonStreaming, ItemStreaming> APIFuture<Req, ItemNonStreaming, ItemStreaming>
+where
+    Req: Default + Clone + Serialize,
+    ItemNonStreaming: for<'de> Deserialize<'de> + 'static,
+    ItemStreaming: for<'de> Deserialize<'de> + 'static,
+{
+    /// Sends the request and waits for a successful response, retrying, timing out
+    /// and aborting as configured by the request options.
+    fn send(&mut self) -> Pin<Box<dyn Future<Output=Result<Response, Error>>>> {
+        let request = self.request.take().unwrap();
+        let max_retries = self.request_options.max_retries.unwrap_or(self.client.max_retries);
+        let timeout = self.request_options.timeout.unwrap_or(self.client.timeout);
+        let signal = self.request_options.signal.clone();
+        let idempotency_key = self.request_options.idempotency_key.clone();
+        let multipart = self.request_options.multipart.clone();
+        let client = self.client.clone();
+
+        Box::pin(self.span.instrument(async move {
+            let send = async {
+                let response = client.send_with_retries(request, multipart.as_ref(), max_retries, timeout).await?;
+                if !response.status().is_success() {
+                    return Err(APIError::from_response(response).await);
+                }
+                Ok(response)
+            };
+
+            APIClient::abortable(signal, send)
+                .await
+                .map_err(|error| error.with_idempotency_key(idempotency_key.as_ref()))
+        }))
+    }
+
+    /// Returns the parsed response together with the metadata of the HTTP response,
+    /// like the request id and the rate limit headers.
+    pub async fn with_response(mut self) -> Result<(ItemNonStreaming, ResponseMeta), Error> {
+        let metrics = self.metrics.clone();
+        let result = self.read_with_response().await;
+        metrics.finish(result.as_ref().err());
+        result
+    }
+
+    async fn read_with_response(mut self) -> Result<(ItemNonStreaming, ResponseMeta), Error> {
+        let started_at = Instant::now();
+        let response = self.send().await?;
+        let mut meta = ResponseMeta::new(&response, started_at.elapsed());
+
+        let signal = self.request_options.signal.clone();
+        let body = APIClient::abortable(signal, async move {
+            response.text().await.map_err(Error::from)
+        }).await?;
+        meta.elapsed = started_at.elapsed();
+        logging::log(LogLevel::Debug, || format!("response body: {body}"));
+        self.span.record_usage(&body);
+        self.metrics.record_body(&body);
+
+        Ok((serde_json::from_str(&body)?, meta))
+    }
+
+    /// Returns the raw HTTP response, without reading or parsing its body.
+    pub async fn as_response(mut self) -> Result<Response, Error> {
+        let result = self.send().await;
+        self.metrics.finish(result.as_ref().err());
+        result
+    }
+}
+
+impl<'a, Req: Default + Clone + Serialize, ItemNonStreaming: for<'de> Deserialize<'de> + Debug + 'static, ItemStreaming: for<'de> Deserialize<'de> + Debug + Any>
 APIFuture<Req, ItemNonStreaming, ItemStreaming>
 {
-    pub fn into_stream(self) -> impl Stream<Item=Result<ItemStreaming, Box<dyn Error>>> + 'a {
-        let request_builder = self.request.unwrap();
+    pub fn into_stream(mut self) -> impl Stream<Item=Result<ItemStreaming, Error>> + 'a {
+        let send = self.send();
+        let timeout = self.request_options.timeout.unwrap_or(self.client.timeout);
+        let idle_timeout = self.request_options.stream_idle_timeout
+            .or(self.client.stream_idle_timeout)
+            .unwrap_or(timeout);
         let is_thread_run = self.request_options.path.starts_with("/threads/") && self.request_options.path.contains("/runs");
+        let signal = self.request_options.signal.clone();
+        let abort = AbortContext {
+            client: self.client.clone(),
+            headers: self.request_options.headers.clone(),
+            cancel_run: is_thread_run && self.request_options.cancel_run_on_abort.unwrap_or(false),
+            run: Arc::new(Mutex::new(None)),
+        };
+        let run = abort.run.clone();
+        let span = self.span.clone();
+        let metrics = self.metrics.clone();
+
+        let events = Box::pin(futures::stream::once(async move {
+            let response = send.await?;
 
-        let mut event_source = EventSource::new(request_builder).expect("Failed to create EventSource");
-
-        futures::stream::poll_fn(move |cx| {
-            loop {
-                let event = futures::ready!(Pin::new(&mut event_source).poll_next(cx));
-                match event {
-                    Some(Ok(Event::Message(message))) if message.data == "[DONE]" => {
-                        return Poll::Ready(None)
-                    },
-                    Some(Ok(Event::Message(message))) => {
-                        if is_thread_run {
-
-                            let data = message.data.clone();
-
-                            // check if ItemStreaming is AssistantStream
-                            // println!("thread event: {:#?}", message);
-                        match message.event.as_str() {
-                                "thread.message.delta" => {
-                                    let enum_data = format!("{{\"message_delta\": {}}}", data);
-                                    let data: Result<ItemStreaming, _> = serde_json::from_str(&enum_data);
-                                    return Poll::Ready(Some(data.map_err(|e| e.into())))
-                                },
-                                "thread.run.step.delta" => {
-                                    let enum_data = format!("{{\"tool_call_delta\": {}}}", data);
-                                    let data: Result<ItemStreaming, _> = serde_json::from_str(&enum_data);
-                                    return Poll::Ready(Some(data.map_err(|e| e.into())))
-                                },
-                                "thread.run.requires_action" => {
-                                    let enum_data = format!("{{\"run\": {}}}", data);
-                                    let data: Result<ItemStreaming, _> = serde_json::from_str(&enum_data);
-                                    return Poll::Ready(Some(data.map_err(|e| e.into())))
-                                },
-                                _ => {
-                                    let enum_data = "{\"text_created\": {\"annotations\": [], \"value\": \"\"}}";
-                                    let data: Result<ItemStreaming, _> = serde_json::from_str(&enum_data);
-                                    return Poll::Ready(Some(data.map_err(|e| e.into())))
-                                },
+            let headers = response.headers().clone();
+            let events = response
+                .bytes_stream()
+                .eventsource()
+                .map_err(Error::from)
+                .try_take_while(|event| future::ready(Ok(event.data != "[DONE]")))
+                .inspect_ok(move |event| {
+                    logging::log(LogLevel::Debug, || format!("event {}: {}", event.event, event.data));
+                    span.record_usage(&event.data);
+                    metrics.record_chunk(&event.event, &event.data);
+                    if is_thread_run && event.event.starts_with("thread.run.") && !event.event.starts_with("thread.run.step.") {
+                        if let Ok(data) = serde_json::from_str::<Value>(&event.data) {
+                            if let (Some(id), Some(thread_id)) = (data["id"].as_str(), data["thread_id"].as_str()) {
+                                *run.lock().unwrap() = Some((thread_id.to_string(), id.to_string()));
                             }
-                            // let data: Result<ItemStreaming, _> = serde_json::from_str(&message.data);
-                            let data: Result<_, Box<dyn Error>> = Err("Not implemented".into());
-                            // println!("event: {:#?}", message);
-                            // println!("data: {:#?}", data);
-                            return Poll::Ready(Some(data.map_err(|e| e.into())))
-                        } else {
-                            let data: Result<ItemStreaming, _> = serde_json::from_str(&message.data);
-                            // println!("event: {:#?}", message);
-                            // println!("data: {:#?}", data);
-                            return Poll::Ready(Some(data.map_err(|e| e.into())))
                         }
                     }
-                    Some(Ok(Event::Open)) => {
-                        continue;
-                    }
-                    Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
-                    None => {
-                        continue;
-                    }
+                })
+                .and_then(move |event| future::ready(Self::decode_event(&event, is_thread_run, &headers)));
+
+            Ok::<_, Error>(with_idle_timeout(events, idle_timeout))
+        }).try_flatten());
+
+        let metrics = self.metrics.clone();
+        let Some(signal) = signal else {
+            return Box::pin(metrics.observe(events.left_stream()));
+        };
+
+        let events = futures::stream::unfold(Some((events, signal, abort)), |state| async move {
+            let (mut events, signal, abort) = state?;
+            tokio::select! {
+                biased;
+                _ = signal.cancelled() => Some((abort.abort().await, None)),
+                event = events.next() => event.map(|event| (event, Some((events, signal, abort)))),
+            }
+        });
+        Box::pin(metrics.observe(events.right_stream()))
+    }
+
+    fn decode_event(message: &Event, is_thread_run: bool, headers: &HeaderMap) -> Result<ItemStreaming, Error> {
+        if message.event == "error" {
+            let error: Option<ErrorObject> = serde_json::from_str(&message.data).ok();
+            return Err(APIError::generate(None, error, Some(message.data.clone()), headers.clone()));
+        }
+
+        if is_thread_run {
+            // Assistant events are told apart by their name, so both are handed to
+            // `AssistantStreamEvent` to pick the type of the payload.
+            let event = format!("{{\"event\":{},\"data\":{}}}", Value::String(message.event.clone()), message.data);
+            Ok(serde_json::from_str(&event)?)
+        } else {
+            // Errors raised after the stream started are sent as a regular event.
+            if message.data.starts_with("{\"error\"") {
+                if let Ok(ErrorResponse { error }) = serde_json::from_str(&message.data) {
+                    return Err(APIError::generate(None, Some(error), None, headers.clone()));
                 }
             }
-        })
+            Ok(serde_json::from_str(&message.data)?)
+        }
     }
 }
 
+/// What is needed to wind down a stream once its signal is cancelled.
+struct AbortContext {
+    client: APIClient,
+    headers: Option<Headers>,
+    /// Whether the run behind an assistant stream should be cancelled on the server as well.
+    cancel_run: bool,
+    /// The `(thread_id, run_id)` of the run streamed so far.
+    run: Arc<Mutex<Option<(String, String)>>>,
+}
+
+impl AbortContext {
+    /// Returns the last item of an aborted stream, [`Error::UserAbort`].
+    ///
+    /// Cancelling the run is best effort: its failure is logged, the stream still ends
+    /// with [`Error::UserAbort`].
+    async fn abort<ItemStreaming: for<'de> Deserialize<'de>>(self) -> Result<ItemStreaming, Error> {
+        let run = self.run.lock().unwrap().take();
+        if let (true, Some((thread_id, run_id))) = (self.cancel_run, run) {
+            let cancelled = self.client.post::<(), Value, ()>(
+                &format!("/threads/{thread_id}/runs/{run_id}/cancel"),
+                Some(RequestOptions {
+                    headers: self.headers,
+                    ..Default::default()
+                }),
+            ).await;
+            if let Err(error) = cancelled {
+                logging::log(LogLevel::Warn, || format!("failed to cancel run {run_id} of the aborted stream: {error}"));
+            }
+        }
 
+        Err(Error::UserAbort)
+    }
+}
+
+/// Fails the stream with [`Error::StreamIdleTimeout`] when no item arrives within `idle_timeout`.
+fn with_idle_timeout<T>(
+    events: impl Stream<Item=Result<T, Error>>,
+    idle_timeout: Duration,
+) -> impl Stream<Item=Result<T, Error>> {
+    futures::stream::unfold(Some(Box::pin(events)), move |events| async move {
+        let mut events = events?;
+        match tokio::time::timeout(idle_timeout, events.next()).await {
+            Ok(Some(event)) => Some((event, Some(events))),
+            Ok(None) => None,
+            Err(_) => Some((Err(Error::StreamIdleTimeout(idle_timeout)), None)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{CancellationToken, InMemoryTransport};
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_stream_fails_with_timeout() {
+        let events = futures::stream::iter(vec![Ok(1)]).chain(futures::stream::pending());
+        let mut events = Box::pin(with_idle_timeout(events, Duration::from_secs(5)));
+
+        assert_eq!(events.next().await.unwrap().unwrap(), 1);
+        assert!(matches!(events.next().await, Some(Err(Error::StreamIdleTimeout(_)))));
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn aborted_streams_end_with_user_abort_when_the_run_cancel_fails() {
+        let transport = Arc::new(InMemoryTransport::new());
+        let run = serde_json::json!({"id": "run_abc", "object": "thread.run", "thread_id": "thread_abc", "status": "queued"});
+        transport
+            .push_response(200, &[("content-type", "text/event-stream")], format!("event: thread.run.created\ndata: {run}\n\n"))
+            .push_response(500, &[], r#"{"error": {"message": "Internal error", "type": "server_error"}}"#);
+        let client = APIClient::with_transport(&transport);
+        let signal = CancellationToken::new();
+
+        let mut events = client.post::<Value, Value, Value>("/threads/thread_abc/runs", Some(RequestOptions {
+            body: Some(serde_json::json!({"assistant_id": "asst_abc", "stream": true})),
+            stream: Some(true),
+            signal: Some(signal.clone()),
+            cancel_run_on_abort: Some(true),
+            ..Default::default()
+        })).into_stream();
+        assert_eq!(events.next().await.unwrap().unwrap()["data"]["id"], "run_abc");
+        signal.cancel();
+
+        assert!(matches!(events.next().await, Some(Err(Error::UserAbort))));
+        assert!(events.next().await.is_none());
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].url.path(), "/threads/thread_abc/runs/run_abc/cancel");
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::Mutex;
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use reqwest::header::HeaderMap;
+use reqwest::{Client, Method, Request, Response, StatusCode, Url};
+use crate::error::Error;
+#[cfg(test)]
+use std::{sync::Arc, time::Duration};
+#[cfg(test)]
+use crate::{core::APIClient, resource::APIResource};
+
+/// Sends the requests built by `APIClient`.
+///
+/// The default transport is [`ReqwestTransport`]; implement this trait to route requests
+/// through a proxy, a recorder or, in tests, the [`InMemoryTransport`].
+pub trait Transport: Debug + Send + Sync {
+    fn send(&self, request: Request) -> BoxFuture<'static, Result<Response, Error>>;
+}
+
+/// Sends requests with a `reqwest::Client`.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: Client) -> Self {
+        ReqwestTransport { client }
+    }
+}
+
+impl From<Client> for ReqwestTransport {
+    fn from(client: Client) -> Self {
+        ReqwestTransport::new(client)
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        let future = self.client.execute(request);
+        Box::pin(async move { Ok(future.await?) })
+    }
+}
+
+/// A request received by the [`InMemoryTransport`].
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub url: Url,
+    pub headers: HeaderMap,
+    /// The body of the request, `None` for streamed bodies.
+    pub body: Option<Bytes>,
+}
+
+#[derive(Debug)]
+enum MockResponse {
+    Response { status: StatusCode, headers: Vec<(String, String)>, body: Bytes },
+    ConnectionError,
+}
+
+/// A transport answering requests with queued responses, without any network access.
+///
+/// Responses are returned in the order they were pushed; every request is recorded.
+#[derive(Debug, Default)]
+pub struct InMemoryTransport {
+    responses: Mutex<VecDeque<MockResponse>>,
+    requests: Mutex<Vec<RecordedRequest>>,
+}
+
+impl InMemoryTransport {
+    pub fn new() -> Self {
+        InMemoryTransport::default()
+    }
+
+    /// Queues a response with the given status, headers and body.
+    pub fn push_response(&self, status: u16, headers: &[(&str, &str)], body: impl Into<Bytes>) -> &Self {
+        self.responses.lock().unwrap().push_back(MockResponse::Response {
+            status: StatusCode::from_u16(status).expect("invalid status code"),
+            headers: headers.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect(),
+            body: body.into(),
+        });
+        self
+    }
+
+    /// Queues a `200` response with a JSON body.
+    pub fn push_json(&self, body: &serde_json::Value) -> &Self {
+        self.push_response(200, &[("content-type", "application/json")], body.to_string())
+    }
+
+    /// Queues a failure to connect to the server.
+    pub fn push_connection_error(&self) -> &Self {
+        self.responses.lock().unwrap().push_back(MockResponse::ConnectionError);
+        self
+    }
+
+    /// The requests received so far.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Transport for InMemoryTransport {
+    fn send(&self, request: Request) -> BoxFuture<'static, Result<Response, Error>> {
+        self.requests.lock().unwrap().push(RecordedRequest {
+            method: request.method().clone(),
+            url: request.url().clone(),
+            headers: request.headers().clone(),
+            body: request.body().and_then(|body| body.as_bytes()).map(Bytes::copy_from_slice),
+        });
+
+        let result = match self.responses.lock().unwrap().pop_front() {
+            Some(MockResponse::Response { status, headers, body }) => {
+                let mut response = http::Response::builder().status(status);
+                for (key, value) in headers {
+                    response = response.header(key, value);
+                }
+                Ok(Response::from(response.body(body).unwrap()))
+            }
+            Some(MockResponse::ConnectionError) => Err(Error::Connection {
+                source: "connection refused".into(),
+                idempotency_key: None,
+            }),
+            None => Err(Error::OpenAI(format!("No response queued for {} {}", request.method(), request.url()))),
+        };
+
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+impl APIClient {
+    /// A client of `http://localhost`, without retries, answered by `transport`.
+    pub(crate) fn with_transport(transport: &Arc<InMemoryTransport>) -> Self {
+        let mut client = APIClient::new("http://localhost".to_string(), 0, Duration::from_secs(10), Client::new());
+        client.transport = transport.clone();
+        client
+    }
+
+    /// A client made by [`APIClient::with_transport`], shared like the client of a resource.
+    pub(crate) fn resource_with_transport(transport: &Arc<InMemoryTransport>) -> APIResource {
+        Arc::new(Mutex::new(APIClient::with_transport(transport)))
+    }
+}
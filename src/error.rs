@@ -1,208 +1,311 @@
-// use std::collections::HashMap;
-// use std::error::Error;
-// use std::fmt;
-//
-// #[derive(Debug)]
-// pub struct OpenAIError;
-//
-// impl fmt::Display for OpenAIError {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         write!(f, "OpenAIError")
-//     }
-// }
-//
-// impl Error for OpenAIError {}
-//
-// #[derive(Debug)]
-// pub struct APIError {
-//     status: Option<u16>,
-//     headers: Option<HashMap<String, String>>,
-//     error: Option<HashMap<String, String>>,
-//     code: Option<String>,
-//     param: Option<String>,
-//     error_type: Option<String>,
-//     request_id: Option<String>,
-// }
-//
-// impl APIError {
-//     fn new(
-//         status: Option<u16>,
-//         error: Option<HashMap<String, String>>,
-//         message: Option<String>,
-//         headers: Option<HashMap<String, String>>,
-//     ) -> Self {
-//         let request_id = headers.as_ref().and_then(|h| h.get("x-request-id").cloned());
-//         let error_map = error.as_ref().map(|e| e.clone());
-//         let code = error_map.as_ref().and_then(|e| e.get("code").cloned());
-//         let param = error_map.as_ref().and_then(|e| e.get("param").cloned());
-//         let error_type = error_map.as_ref().and_then(|e| e.get("type").cloned());
-//
-//         APIError {
-//             status,
-//             headers,
-//             error: error_map,
-//             code,
-//             param,
-//             error_type,
-//             request_id,
-//         }
-//     }
-//
-//     fn make_message(status: Option<u16>, error: Option<&HashMap<String, String>>, message: Option<&String>) -> String {
-//         let msg = error
-//             .and_then(|e| e.get("message"))
-//             .map(|m| m.clone())
-//             .unwrap_or_else(|| message.cloned().unwrap_or_else(|| "".to_string()));
-//
-//         match (status, msg.as_str()) {
-//             (Some(status), msg) if !msg.is_empty() => format!("{} {}", status, msg),
-//             (Some(status), _) => format!("{} status code (no body)", status),
-//             (_, msg) if !msg.is_empty() => msg.to_string(),
-//             _ => "(no status code or body)".to_string(),
-//         }
-//     }
-//
-//     fn generate(
-//         status: Option<u16>,
-//         error_response: Option<HashMap<String, String>>,
-//         message: Option<String>,
-//         headers: Option<HashMap<String, String>>,
-//     ) -> Box<dyn Error> {
-//         if status.is_none() {
-//             return Box::new(APIConnectionError::new(cast_to_error(error_response)));
-//         }
-//
-//         let error = error_response.as_ref().and_then(|e| e.get("error")).cloned();
-//
-//         match status {
-//             Some(400) => Box::new(BadRequestError::new(status, error, message, headers)),
-//             Some(401) => Box::new(AuthenticationError::new(status, error, message, headers)),
-//             Some(403) => Box::new(PermissionDeniedError::new(status, error, message, headers)),
-//             Some(404) => Box::new(NotFoundError::new(status, error, message, headers)),
-//             Some(409) => Box::new(ConflictError::new(status, error, message, headers)),
-//             Some(422) => Box::new(UnprocessableEntityError::new(status, error, message, headers)),
-//             Some(429) => Box::new(RateLimitError::new(status, error, message, headers)),
-//             Some(status) if status >= 500 => Box::new(InternalServerError::new(status, error, message, headers)),
-//             _ => Box::new(APIError::new(status, error_response, message, headers)),
-//         }
-//     }
-// }
-//
-// #[derive(Debug)]
-// pub struct APIUserAbortError {
-//     status: Option<u16>,
-//     message: Option<String>,
-// }
-//
-// impl APIUserAbortError {
-//     fn new(message: Option<String>) -> Self {
-//         APIUserAbortError {
-//             status: None,
-//             message: Some(message.unwrap_or_else(|| "Request was aborted.".to_string())),
-//         }
-//     }
-// }
-//
-// impl fmt::Display for APIUserAbortError {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         write!(f, "APIUserAbortError: {:?}", self.message)
-//     }
-// }
-//
-// impl Error for APIUserAbortError {}
-//
-// #[derive(Debug)]
-// pub struct APIConnectionError {
-//     status: Option<u16>,
-//     message: Option<String>,
-//     cause: Option<Box<dyn Error>>,
-// }
-//
-// impl APIConnectionError {
-//     fn new(cause: Option<Box<dyn Error>>) -> Self {
-//         APIConnectionError {
-//             status: None,
-//             message: Some("Connection error.".to_string()),
-//             cause,
-//         }
-//     }
-// }
-//
-// impl fmt::Display for APIConnectionError {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         write!(f, "APIConnectionError: {:?}", self.message)
-//     }
-// }
-//
-// impl Error for APIConnectionError {}
-//
-// #[derive(Debug)]
-// pub struct APIConnectionTimeoutError {
-//     message: Option<String>,
-// }
-//
-// impl APIConnectionTimeoutError {
-//     fn new(message: Option<String>) -> Self {
-//         APIConnectionTimeoutError {
-//             message: Some(message.unwrap_or_else(|| "Request timed out.".to_string())),
-//         }
-//     }
-// }
-//
-// impl fmt::Display for APIConnectionTimeoutError {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         write!(f, "APIConnectionTimeoutError: {:?}", self.message)
-//     }
-// }
-//
-// impl Error for APIConnectionTimeoutError {}
-//
-// macro_rules! define_error {
-//     ($name:ident, $status:expr) => {
-//         #[derive(Debug)]
-//         pub struct $name {
-//             status: u16,
-//             error: Option<HashMap<String, String>>,
-//             message: Option<String>,
-//             headers: Option<HashMap<String, String>>,
-//         }
-//
-//         impl $name {
-//             fn new(
-//                 status: Option<u16>,
-//                 error: Option<String>,
-//                 message: Option<String>,
-//                 headers: Option<HashMap<String, String>>,
-//             ) -> Self {
-//                 $name {
-//                     status: $status,
-//                     error: error.map(|e| [("error".to_string(), e)].iter().cloned().collect()),
-//                     message,
-//                     headers,
-//                 }
-//             }
-//         }
-//
-//         impl fmt::Display for $name {
-//             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//                 write!(f, "{}: {:?}", stringify!($name), self.message)
-//             }
-//         }
-//
-//         impl Error for $name {}
-//     };
-// }
-//
-// define_error!(BadRequestError, 400);
-// define_error!(AuthenticationError, 401);
-// define_error!(PermissionDeniedError, 403);
-// define_error!(NotFoundError, 404);
-// define_error!(ConflictError, 409);
-// define_error!(UnprocessableEntityError, 422);
-// define_error!(RateLimitError, 429);
-// define_error!(InternalServerError, 500);
-//
-// fn cast_to_error(_error_response: Option<HashMap<String, String>>) -> Option<Box<dyn Error>> {
-//     // Implement the logic to cast error_response to an error here.
-//     None
-// }
\ No newline at end of file
+use std::error::Error as StdError;
+use std::fmt;
+use std::time::Duration;
+use reqwest::header::HeaderMap;
+use reqwest::{Response, StatusCode};
+use crate::ErrorResponse;
+pub use crate::shared::ErrorObject;
+
+/// The details of a non-success response returned by the API.
+#[derive(Debug, Clone)]
+pub struct APIError {
+    /// The HTTP status code of the response, `None` for errors sent inside an event stream.
+    pub status: Option<StatusCode>,
+
+    /// The parsed `error` object of the response body, if the body could be parsed.
+    pub error: Option<ErrorObject>,
+
+    /// The headers of the response.
+    pub headers: HeaderMap,
+
+    /// The `x-request-id` header of the response, useful when reporting issues to OpenAI.
+    pub request_id: Option<String>,
+
+    /// A human readable message, built from the error object or the raw body.
+    pub message: String,
+
+    /// The `Idempotency-Key` sent with the request, if any.
+    pub idempotency_key: Option<String>,
+}
+
+impl APIError {
+    pub fn new(status: Option<StatusCode>, error: Option<ErrorObject>, message: Option<String>, headers: HeaderMap) -> Self {
+        let request_id = headers
+            .get("x-request-id")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string());
+
+        APIError {
+            message: Self::make_message(status, error.as_ref(), message),
+            status,
+            error,
+            headers,
+            request_id,
+            idempotency_key: None,
+        }
+    }
+
+    fn make_message(status: Option<StatusCode>, error: Option<&ErrorObject>, message: Option<String>) -> String {
+        let msg = error
+            .map(|error| error.message.clone())
+            .or(message)
+            .unwrap_or_default();
+
+        match status {
+            Some(status) if !msg.is_empty() => format!("{} {}", status.as_u16(), msg),
+            Some(status) => format!("{} status code (no body)", status.as_u16()),
+            None if !msg.is_empty() => msg,
+            None => "(no status code or body)".to_string(),
+        }
+    }
+
+    /// Maps the status code of the response to the matching [`Error`] variant.
+    pub fn generate(status: Option<StatusCode>, error: Option<ErrorObject>, message: Option<String>, headers: HeaderMap) -> Error {
+        let error = APIError::new(status, error, message, headers);
+
+        match status.map(|status| status.as_u16()) {
+            Some(400) => Error::BadRequest(error),
+            Some(401) => Error::Authentication(error),
+            Some(403) => Error::PermissionDenied(error),
+            Some(404) => Error::NotFound(error),
+            Some(409) => Error::Conflict(error),
+            Some(422) => Error::UnprocessableEntity(error),
+            Some(429) => Error::RateLimit(error),
+            Some(status) if status >= 500 => Error::InternalServer(error),
+            _ => Error::API(error),
+        }
+    }
+
+    /// Reads the body of a non-success response and turns it into an [`Error`].
+    ///
+    /// Bodies shaped like `{"error": {...}}` are parsed into an [`ErrorObject`],
+    /// any other body is kept as the error message.
+    pub(crate) async fn from_response(response: Response) -> Error {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = match response.text().await {
+            Ok(body) => body,
+            Err(error) => return error.into(),
+        };
+
+        match serde_json::from_str::<ErrorResponse>(&body) {
+            Ok(ErrorResponse { error }) => APIError::generate(Some(status), Some(error), None, headers),
+            Err(_) => APIError::generate(Some(status), None, Some(body), headers),
+        }
+    }
+}
+
+/// Errors returned by the client.
+#[derive(Debug)]
+pub enum Error {
+    /// The API returned a `400` status code.
+    BadRequest(APIError),
+    /// The API returned a `401` status code.
+    Authentication(APIError),
+    /// The API returned a `403` status code.
+    PermissionDenied(APIError),
+    /// The API returned a `404` status code.
+    NotFound(APIError),
+    /// The API returned a `409` status code.
+    Conflict(APIError),
+    /// The API returned a `422` status code.
+    UnprocessableEntity(APIError),
+    /// The API returned a `429` status code.
+    RateLimit(APIError),
+    /// The API returned a `5xx` status code.
+    InternalServer(APIError),
+    /// The API returned any other non-success status code.
+    API(APIError),
+    /// The request could not reach the API.
+    Connection {
+        source: Box<dyn StdError + Send + Sync>,
+        idempotency_key: Option<String>,
+    },
+    /// The request did not complete before the timeout elapsed.
+    ConnectionTimeout {
+        idempotency_key: Option<String>,
+    },
+    /// No event was received on a stream for longer than the idle timeout.
+    StreamIdleTimeout(Duration),
+    /// A polling helper did not reach a terminal state before its `poll_timeout`.
+    PollTimeout(Duration),
+    /// The request was aborted by the caller.
+    UserAbort,
+    /// The response body could not be deserialized.
+    Deserialize(serde_json::Error),
+    /// Any other error raised by the client itself.
+    OpenAI(String),
+}
+
+impl Error {
+    /// The details of the API response, if this error was caused by a non-success status code.
+    pub fn api_error(&self) -> Option<&APIError> {
+        match self {
+            Error::BadRequest(error)
+            | Error::Authentication(error)
+            | Error::PermissionDenied(error)
+            | Error::NotFound(error)
+            | Error::Conflict(error)
+            | Error::UnprocessableEntity(error)
+            | Error::RateLimit(error)
+            | Error::InternalServer(error)
+            | Error::API(error) => Some(error),
+            _ => None,
+        }
+    }
+
+    /// The HTTP status code of the response, if any.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.api_error().and_then(|error| error.status)
+    }
+
+    /// The `x-request-id` of the response, if any.
+    pub fn request_id(&self) -> Option<&str> {
+        self.api_error().and_then(|error| error.request_id.as_deref())
+    }
+
+    /// The `Idempotency-Key` of the failed request, if any.
+    ///
+    /// Retrying a side-effecting call with the same key lets the API de-duplicate it.
+    pub fn idempotency_key(&self) -> Option<&str> {
+        match self {
+            Error::Connection { idempotency_key, .. } | Error::ConnectionTimeout { idempotency_key } => {
+                idempotency_key.as_deref()
+            }
+            _ => self.api_error().and_then(|error| error.idempotency_key.as_deref()),
+        }
+    }
+
+    pub(crate) fn with_idempotency_key(mut self, key: Option<&String>) -> Self {
+        let key = key.cloned();
+        match &mut self {
+            Error::Connection { idempotency_key, .. } | Error::ConnectionTimeout { idempotency_key } => {
+                *idempotency_key = key;
+            }
+            Error::BadRequest(error)
+            | Error::Authentication(error)
+            | Error::PermissionDenied(error)
+            | Error::NotFound(error)
+            | Error::Conflict(error)
+            | Error::UnprocessableEntity(error)
+            | Error::RateLimit(error)
+            | Error::InternalServer(error)
+            | Error::API(error) => error.idempotency_key = key,
+            _ => {}
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connection { .. } => write!(f, "Connection error."),
+            Error::ConnectionTimeout { .. } => write!(f, "Request timed out."),
+            Error::StreamIdleTimeout(timeout) => write!(f, "Stream timed out after {timeout:?} without events."),
+            Error::PollTimeout(timeout) => write!(f, "Polling timed out after {timeout:?}."),
+            Error::UserAbort => write!(f, "Request was aborted."),
+            Error::Deserialize(error) => write!(f, "Failed to deserialize the response: {error}"),
+            Error::OpenAI(message) => write!(f, "{message}"),
+            _ => write!(f, "{}", self.api_error().map(|error| error.message.as_str()).unwrap_or_default()),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Connection { source, .. } => Some(source.as_ref()),
+            Error::Deserialize(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(error: reqwest::Error) -> Self {
+        if error.is_timeout() {
+            Error::ConnectionTimeout { idempotency_key: None }
+        } else if error.is_builder() {
+            Error::OpenAI(error.to_string())
+        } else {
+            Error::Connection { source: Box::new(error), idempotency_key: None }
+        }
+    }
+}
+
+impl From<eventsource_stream::EventStreamError<reqwest::Error>> for Error {
+    fn from(error: eventsource_stream::EventStreamError<reqwest::Error>) -> Self {
+        match error {
+            eventsource_stream::EventStreamError::Transport(error) => error.into(),
+            error => Error::Connection { source: Box::new(error), idempotency_key: None },
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Deserialize(error)
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::OpenAI(message)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::OpenAI(message.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_maps_status_codes_to_variants() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "req_123".parse().unwrap());
+        let error_object = ErrorObject {
+            code: Some("invalid_api_key".to_string()),
+            message: "Incorrect API key provided".to_string(),
+            param: None,
+            kind: "invalid_request_error".to_string(),
+        };
+
+        let error = APIError::generate(Some(StatusCode::UNAUTHORIZED), Some(error_object), None, headers);
+        assert!(matches!(error, Error::Authentication(_)));
+        assert_eq!(error.status(), Some(StatusCode::UNAUTHORIZED));
+        assert_eq!(error.request_id(), Some("req_123"));
+        assert_eq!(error.to_string(), "401 Incorrect API key provided");
+
+        let error = APIError::generate(Some(StatusCode::BAD_GATEWAY), None, None, HeaderMap::new());
+        assert!(matches!(error, Error::InternalServer(_)));
+        assert_eq!(error.to_string(), "502 status code (no body)");
+
+        let error = APIError::generate(Some(StatusCode::IM_A_TEAPOT), None, Some("teapot".to_string()), HeaderMap::new());
+        assert!(matches!(error, Error::API(_)));
+    }
+
+    #[tokio::test]
+    async fn from_response_parses_error_bodies() {
+        let body = r#"{"error": {"message": "No such model", "type": "invalid_request_error", "param": "model", "code": "model_not_found"}}"#;
+        let response = Response::from(http::Response::builder().status(404).body(body).unwrap());
+
+        let error = APIError::from_response(response).await;
+        let api_error = error.api_error().unwrap();
+        assert!(matches!(error, Error::NotFound(_)));
+        assert_eq!(api_error.error.as_ref().unwrap().code.as_deref(), Some("model_not_found"));
+        assert_eq!(api_error.message, "404 No such model");
+
+        let response = Response::from(http::Response::builder().status(502).body("Bad Gateway").unwrap());
+
+        let error = APIError::from_response(response).await;
+        assert!(matches!(error, Error::InternalServer(_)));
+        assert!(error.api_error().unwrap().error.is_none());
+        assert_eq!(error.to_string(), "502 Bad Gateway");
+    }
+}
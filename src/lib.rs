@@ -1,12 +1,15 @@
 pub mod core;
-mod openai_error;
-mod pagination;
+pub mod pagination;
 mod resource;
 mod shared;
 pub mod error;
 pub mod library;
 pub mod resources;
 pub mod streaming;
+#[cfg(test)]
+mod test_support;
+
+pub use error::{Error, ErrorObject};
 
 // use resources::chat;
 use std::cell::RefCell;
@@ -19,9 +22,11 @@ use crate::resources::completions::Completions;
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
-use crate::core::{APIClient, Headers};
+use crate::core::{APIClient, Headers, Middleware, Transport};
 use crate::resources::beta::Beta;
 use crate::resources::chat::Chat;
+use crate::resources::embeddings::Embeddings;
+use crate::resources::files::Files;
 
 #[derive(Debug, Clone)]
 pub struct ClientOptions {
@@ -45,11 +50,28 @@ pub struct ClientOptions {
     /// much longer than this timeout before the promise succeeds or fails.
     pub timeout: Option<Duration>,
 
-    /// An HTTP agent used to manage HTTP(S) connections.
-    pub http_agent: Option<APIClient>,
+    /// The maximum amount of time to wait between two events of a streaming response
+    /// before failing the stream.
+    ///
+    /// Defaults to `timeout`.
+    pub stream_idle_timeout: Option<Duration>,
+
+    /// A pre-configured `reqwest::Client` used to manage HTTP(S) connections,
+    /// e.g. with a proxy or custom TLS settings.
+    pub http_agent: Option<reqwest::Client>,
 
-    /// Specify a custom `fetch` function implementation.
-    pub fetch: Option<APIClient>,
+    /// Specify a custom transport used to send every request, e.g. the
+    /// `InMemoryTransport` in tests. Takes precedence over `http_agent`.
+    pub fetch: Option<Arc<dyn Transport>>,
+
+    /// Hooks called around every HTTP request, in order. See `Middleware`.
+    pub middleware: Vec<Arc<dyn Middleware>>,
+
+    /// The meter recording the GenAI metrics of the client. See `Metrics`.
+    ///
+    /// Defaults to the meter of the global `MeterProvider`.
+    #[cfg(feature = "opentelemetry")]
+    pub meter: Option<opentelemetry::metrics::Meter>,
 
     /// The maximum number of times that the client will retry a request in case of a
     /// temporary failure, like a network error or a 5XX error from the server.
@@ -72,6 +94,10 @@ pub struct ClientOptions {
     /// By default, client-side use of this library is not allowed, as it risks exposing your secret API credentials to attackers.
     /// Only set this option to `true` if you understand the risks and have appropriate mitigations in place.
     pub dangerously_allow_browser: bool,
+
+    /// Don't send the `X-Stainless-*` headers describing the OS, architecture and
+    /// runtime of the client.
+    pub disable_platform_headers: bool,
 }
 
 impl ClientOptions {
@@ -82,12 +108,17 @@ impl ClientOptions {
             project: env::var("OPENAI_PROJECT_ID").ok(),
             base_url: env::var("OPENAI_BASE_URL").ok(),
             timeout: Some(Duration::from_secs(600)),
+            stream_idle_timeout: None,
             http_agent: None,
             fetch: None,
+            middleware: Vec::new(),
+            #[cfg(feature = "opentelemetry")]
+            meter: None,
             max_retries: Some(2),
             default_headers: None,
             default_query: None,
             dangerously_allow_browser: false,
+            disable_platform_headers: false,
         }
     }
 
@@ -105,6 +136,8 @@ pub struct OpenAI {
     pub client: APIClient,
     pub completions: Completions,
     pub chat: Chat,
+    pub embeddings: Embeddings,
+    pub files: Files,
     pub beta: Beta,
 }
 
@@ -123,7 +156,7 @@ impl OpenAI {
             opts.base_url.clone().unwrap_or("https://api.openai.com/v1".to_string()),
             opts.max_retries.unwrap_or(2),
             opts.timeout.unwrap_or(Duration::from_secs(600)),
-            reqwest::Client::new(),
+            opts.http_agent.clone().unwrap_or_default(),
         );
 
         let mut openai = OpenAI {
@@ -134,14 +167,34 @@ impl OpenAI {
             client,
             completions: Completions::new(),
             chat: Chat::new(),
+            embeddings: Embeddings::new(),
+            files: Files::new(),
             beta: Beta::new(),
         };
 
+        if let Some(transport) = &openai.options.fetch {
+            openai.client.transport = transport.clone();
+        }
+        openai.client.middleware = openai.options.middleware.clone();
         openai.client.additional_auth_headers = Some(openai.auth_headers());
+        openai.client.custom_headers = Some(openai.default_headers());
+        if openai.options.disable_platform_headers {
+            openai.client.platform_headers = None;
+        }
+        openai.client.default_query = openai.options.default_query.clone();
+        openai.client.stream_idle_timeout = openai.options.stream_idle_timeout;
+        #[cfg(feature = "opentelemetry")]
+        {
+            let meter = openai.options.meter.clone()
+                .unwrap_or_else(|| opentelemetry::global::meter("open_ai"));
+            openai.client.metrics = crate::core::Metrics::new(&meter);
+        }
         // openai.completions.client = Some(Rc::new(RefCell::new(openai.client.clone())));
         openai.completions.client = Some(Arc::new(Mutex::new(openai.client.clone())));
         // openai.chat.set_client(Rc::new(RefCell::new(openai.client.clone())));
         openai.chat.set_client(Arc::new(Mutex::new(openai.client.clone())));
+        openai.embeddings.client = Some(Arc::new(Mutex::new(openai.client.clone())));
+        openai.files.client = Some(Arc::new(Mutex::new(openai.client.clone())));
         // openai.beta.set_client(Rc::new(RefCell::new(openai.client.clone())));
         openai.beta.set_client(Arc::new(Mutex::new(openai.client.clone())));
 
@@ -152,21 +205,21 @@ impl OpenAI {
         OpenAI::new(ClientOptions::new())
     }
 
-    // fn default_headers(&self) -> HashMap<String, String> {
-    //     let mut headers = HashMap::new();
-    //     if let Some(ref org) = self.organization {
-    //         headers.insert("OpenAI-Organization".to_string(), org.clone());
-    //     }
-    //     if let Some(ref proj) = self.project {
-    //         headers.insert("OpenAI-Project".to_string(), proj.clone());
-    //     }
-    //     if let Some(ref default_headers) = self.options.default_headers {
-    //         for (key, value) in default_headers {
-    //             headers.insert(key.clone(), value.clone());
-    //         }
-    //     }
-    //     headers
-    // }
+    fn default_headers(&self) -> Headers {
+        let mut headers = HashMap::new();
+        if let Some(ref org) = self.organization {
+            headers.insert("OpenAI-Organization".to_string(), Some(org.clone()));
+        }
+        if let Some(ref proj) = self.project {
+            headers.insert("OpenAI-Project".to_string(), Some(proj.clone()));
+        }
+        if let Some(ref default_headers) = self.options.default_headers {
+            for (key, value) in default_headers {
+                headers.insert(key.clone(), Some(value.clone()));
+            }
+        }
+        headers
+    }
 
     fn auth_headers(&self) -> Headers {
         let mut headers = HashMap::new();
@@ -212,14 +265,6 @@ impl OpenAI {
     //     self.build_request(reqwest::Method::POST, "chat/completions", Some(&params)).send().await
     // }
     //
-    // pub async fn embeddings(&self, params: HashMap<String, String>) -> Result<reqwest::Response, reqwest::Error> {
-    //     self.build_request(reqwest::Method::POST, "embeddings", Some(&params)).send().await
-    // }
-    //
-    // pub async fn files(&self) -> Result<reqwest::Response, reqwest::Error> {
-    //     self.build_request(reqwest::Method::GET, "files", None).send().await
-    // }
-    //
     // pub async fn images(&self, params: HashMap<String, String>) -> Result<reqwest::Response, reqwest::Error> {
     //     self.build_request(reqwest::Method::POST, "images/generations", Some(&params)).send().await
     // }
@@ -254,14 +299,6 @@ pub struct ErrorResponse {
     pub error: ErrorObject,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ErrorObject {
-    pub message: String,
-    pub type_: String,
-    pub param: Option<String>,
-    pub code: Option<String>,
-}
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDefinition {
     pub name: String,
@@ -313,10 +350,9 @@ mod tests {
     use std::env;
     use std::error::Error;
     use futures::StreamExt;
-    use reqwest_eventsource::Event;
     use serde_json::json;
     use crate::{ClientOptions, OpenAI};
-    use crate::library::assistant_stream::AssistantStream;
+    use crate::resources::beta::assistants::AssistantStreamEvent;
     use crate::resources::beta::assistants::Assistant;
     use crate::resources::beta::assistants::AssistantTool::{self, CodeInterpreter};
     use crate::resources::beta::assistants::assistant::ToolResources;
@@ -743,7 +779,7 @@ mod tests {
 
         while let Some(event) = run.next().await {
             match event {
-                Ok(AssistantStream::MessageDelta(message)) => {
+                Ok(AssistantStreamEvent::ThreadMessageDelta(message)) => {
                     message.delta.content.iter().for_each(|content| {
                         for delta in content.iter() {
                             match delta {
@@ -779,10 +815,10 @@ mod tests {
                     //     print!("{}", text);
                     // }
                 },
-                Ok(AssistantStream::ToolCallDelta(tool_call)) => {
+                Ok(AssistantStreamEvent::ThreadRunStepDelta(tool_call)) => {
                     println!("tool_call: {:?}", tool_call);
                 }
-                Ok(AssistantStream::Run(message)) => {
+                Ok(AssistantStreamEvent::ThreadRunRequiresAction(message)) => {
                     println!("run: {:?}", message);
                 },
                 Err(_) => {
@@ -853,7 +889,7 @@ mod tests {
 
         while let Some(event) = run.next().await {
             match event {
-                Ok(AssistantStream::MessageDelta(message)) => {
+                Ok(AssistantStreamEvent::ThreadMessageDelta(message)) => {
                     message.delta.content.iter().for_each(|content| {
                         for delta in content.iter() {
                             match delta {
@@ -889,10 +925,10 @@ mod tests {
                     //     print!("{}", text);
                     // }
                 },
-                Ok(AssistantStream::ToolCallDelta(tool_call)) => {
+                Ok(AssistantStreamEvent::ThreadRunStepDelta(tool_call)) => {
                     println!("tool_call: {:?}", tool_call);
                 }
-                Ok(AssistantStream::Run(message)) => {
+                Ok(AssistantStreamEvent::ThreadRunRequiresAction(message)) => {
                     println!("run: {:?}", message);
                     let tool_call_id: String = if let Some(first) = message.required_action.unwrap_or_default().submit_tool_outputs.tool_calls.first() {
                         first.id.clone()
@@ -913,7 +949,7 @@ mod tests {
 
                     while let Some(evt) = stream.next().await {
                         match evt {
-                            Ok(AssistantStream::MessageDelta(message)) => {
+                            Ok(AssistantStreamEvent::ThreadMessageDelta(message)) => {
                                 message.delta.content.iter().for_each(|content| {
                                     for delta in content.iter() {
                                         match delta {
@@ -0,0 +1,103 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Serialize;
+use crate::error::Error;
+use crate::core::streaming::APIFuture;
+use crate::library::assistant_stream::{AssistantStream, AssistantStreamRunner};
+use crate::resources::beta::assistants::AssistantStreamEvent;
+use crate::resources::beta::threads::{ImageFile, Message, MessageDelta, Run, Text, TextDelta};
+use crate::resources::beta::threads::runs::steps::{RunStep, RunStepDelta, ToolCall, ToolCallDelta};
+
+/// Callbacks for the events of an assistant stream, to react to a run without matching
+/// on the raw events. Every method does nothing by default.
+///
+/// Handlers are driven by [`AssistantStreamRunner::with_handler`], e.g. from `Runs::stream`:
+///
+/// ```ignore
+/// let run = openai.beta.threads.runs
+///     .stream(&thread.id, params, None)
+///     .with_handler(&mut handler)
+///     .await?;
+/// ```
+#[async_trait(?Send)]
+pub trait AssistantEventHandler {
+    /// Called for every raw event, before the callbacks derived from it.
+    async fn on_event(&mut self, _event: &AssistantStreamEvent) {}
+
+    async fn on_run_step_created(&mut self, _run_step: &RunStep) {}
+    async fn on_run_step_delta(&mut self, _delta: &RunStepDelta, _snapshot: &RunStep) {}
+    async fn on_run_step_done(&mut self, _run_step: &RunStep) {}
+
+    async fn on_tool_call_created(&mut self, _tool_call: &ToolCall) {}
+    async fn on_tool_call_delta(&mut self, _delta: &ToolCallDelta, _snapshot: &ToolCall) {}
+    async fn on_tool_call_done(&mut self, _tool_call: &ToolCall) {}
+
+    async fn on_message_created(&mut self, _message: &Message) {}
+    async fn on_message_delta(&mut self, _delta: &MessageDelta, _snapshot: &Message) {}
+    async fn on_message_done(&mut self, _message: &Message) {}
+
+    async fn on_text_created(&mut self, _text: &Text) {}
+    async fn on_text_delta(&mut self, _delta: &TextDelta, _snapshot: &Text) {}
+    async fn on_text_done(&mut self, _text: &Text) {}
+
+    async fn on_image_file_done(&mut self, _image_file: &ImageFile) {}
+
+    /// Called once the stream ended, with the run in its last state.
+    async fn on_end(&mut self, _run: &Run) {}
+
+    /// Called with the error that ended the stream, including [`Error::UserAbort`].
+    async fn on_error(&mut self, _error: &Error) {}
+}
+
+/// Ignores every event.
+impl AssistantEventHandler for () {}
+
+impl AssistantStreamRunner {
+    /// Consumes the stream, calling `handler` for each of its events, and returns the final run.
+    pub async fn with_handler<H: AssistantEventHandler + ?Sized>(mut self, handler: &mut H) -> Result<Run, Error> {
+        // The final run is emitted right before the end of the stream.
+        let mut final_run = None;
+        while let Some(event) = self.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    handler.on_error(&error).await;
+                    return Err(error);
+                }
+            };
+
+            match &event {
+                AssistantStream::Event(event) => handler.on_event(event).await,
+                AssistantStream::RunStepCreated(run_step) => handler.on_run_step_created(run_step).await,
+                AssistantStream::RunStepDelta { delta, snapshot } => handler.on_run_step_delta(delta, snapshot).await,
+                AssistantStream::RunStepDone { run_step, .. } => handler.on_run_step_done(run_step).await,
+                AssistantStream::ToolCallCreated(tool_call) => handler.on_tool_call_created(tool_call).await,
+                AssistantStream::ToolCallDelta { delta, snapshot } => handler.on_tool_call_delta(delta, snapshot).await,
+                AssistantStream::ToolCallDone(tool_call) => handler.on_tool_call_done(tool_call).await,
+                AssistantStream::MessageCreated(message) => handler.on_message_created(message).await,
+                AssistantStream::MessageDelta { delta, snapshot } => handler.on_message_delta(delta, snapshot).await,
+                AssistantStream::MessageDone(message) => handler.on_message_done(message).await,
+                AssistantStream::TextCreated(text) => handler.on_text_created(text).await,
+                AssistantStream::TextDelta { delta, snapshot } => handler.on_text_delta(delta, snapshot).await,
+                AssistantStream::TextDone { content, .. } => handler.on_text_done(content).await,
+                AssistantStream::ImageFileDone { content, .. } => handler.on_image_file_done(content).await,
+                AssistantStream::Run(run) => final_run = Some(run.clone()),
+                AssistantStream::End => {
+                    if let Some(run) = &final_run {
+                        handler.on_end(run).await;
+                    }
+                }
+                AssistantStream::Connect => {}
+            }
+        }
+
+        self.final_run().await
+    }
+}
+
+impl<Req: Default + Clone + Serialize + 'static> APIFuture<Req, (), AssistantStreamEvent> {
+    /// Sends the request and calls `handler` for each event of the stream, returning the final run.
+    pub async fn with_handler<H: AssistantEventHandler + ?Sized>(self, handler: &mut H) -> Result<Run, Error> {
+        self.into_assistant_stream().with_handler(handler).await
+    }
+}
@@ -1,39 +1,48 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
-use crate::resources::beta::threads::{ImageFile, Message, MessageDelta, MessageDeltaEvent, Run, Text, TextDelta};
-use crate::resources::beta::threads::runs::steps::{RunStep, RunStepDelta, RunStepDeltaEvent, ToolCall, ToolCallDelta};
+use serde_json::{Map, Value};
+use crate::error::Error;
+use crate::core::streaming::APIFuture;
+use crate::resources::beta::assistants::AssistantStreamEvent;
+use crate::resources::beta::threads::{ImageFile, Message, MessageContent, MessageContentDelta, MessageDelta, Run, Text, TextDelta, TextDeltaBlock};
+use crate::resources::beta::threads::runs::steps::{run_step, run_step_delta, RunStep, RunStepDelta, ToolCall, ToolCallDelta, ToolCallDeltaObject};
 
+/// The events emitted by an [`AssistantStreamRunner`], built from the raw server-sent events
+/// with the snapshots accumulated so far.
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-// pub struct AssistantStream {}
 pub enum AssistantStream {
     //New event structure
     MessageCreated(Message),
-    MessageDelta(MessageDeltaEvent), // { message: MessageDelta, snapshot: Message },
-    MessageDone{ message: Message },
+    MessageDelta { delta: MessageDelta, snapshot: Message },
+    MessageDone(Message),
 
     RunStepCreated(RunStep),
-    RunStepDelta{delta: RunStepDelta, snapshot: RunStep},
-    RunStepDone{ run_step: RunStep, snapshot: RunStep },
+    RunStepDelta { delta: RunStepDelta, snapshot: RunStep },
+    RunStepDone { run_step: RunStep, snapshot: RunStep },
 
     ToolCallCreated(ToolCall),
-    ToolCallDelta(RunStepDeltaEvent), // thread.run.step.delta
+    ToolCallDelta { delta: ToolCallDelta, snapshot: ToolCall },
     ToolCallDone(ToolCall),
 
     TextCreated(Text),
-    TextDelta{ delta: TextDelta, snapshot: Text },
-    TextDone{ content: Text, snapshot: Message },
+    TextDelta { delta: TextDelta, snapshot: Text },
+    TextDone { content: Text, snapshot: Message },
 
     // No created or delta as this is not streamed
-    ImageFileDone{ content: ImageFile, snapshot: Message },
+    ImageFileDone { content: ImageFile, snapshot: Message },
 
-    Event, // (AssistantStreamEvent),
+    /// Every raw event, emitted before the events derived from it.
+    Event(AssistantStreamEvent),
 
     // AbstractAssistantRunnerEvents
     #[default]
     Connect,
+    /// The final run, emitted once the stream ended.
     Run(Run),
-    Error, // (OpenAIError),
-    Abort, // (APIUserAbortError),
     End,
 }
 
@@ -43,726 +52,696 @@ pub struct RunCreateParamsBaseStream {}
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct RunSubmitToolOutputsParamsStream {}
 
-// import {
-//   TextContentBlock,
-//   ImageFileContentBlock,
-//   Message,
-//   MessageContentDelta,
-//   Text,
-//   ImageFile,
-//   TextDelta,
-//   Messages,
-//   MessageContent,
-// } from "../resources/beta/threads/messages";
-// import * as Core from "../core";
-// import { RequestOptions } from "../core";
-// import {
-//   Run,
-//   RunCreateParamsBase,
-//   RunCreateParamsStreaming,
-//   Runs,
-//   RunSubmitToolOutputsParamsBase,
-//   RunSubmitToolOutputsParamsStreaming,
-// } from "../resources/beta/threads/runs/runs";
-// import {
-//   AbstractAssistantRunnerEvents,
-//   AbstractAssistantStreamRunner,
-// } from './AbstractAssistantStreamRunner';
-// import { type ReadableStream } from "../_shims/index";
-// import { Stream } from "../streaming";
-// import { APIUserAbortError, OpenAIError } from "../error";
-// import {
-//   AssistantStreamEvent,
-//   MessageStreamEvent,
-//   RunStepStreamEvent,
-//   RunStreamEvent,
-// } from "../resources/beta/assistants";
-// import { RunStep, RunStepDelta, ToolCall, ToolCallDelta } from "../resources/beta/threads/runs/steps";
-// import { ThreadCreateAndRunParamsBase, Threads } from "../resources/beta/threads/threads";
-// import MessageDelta = Messages.MessageDelta;
-//
-// export interface AssistantStreamEvents extends AbstractAssistantRunnerEvents {
-//   //New event structure
-//   messageCreated: (message: Message) => void;
-//   messageDelta: (message: MessageDelta, snapshot: Message) => void;
-//   messageDone: (message: Message) => void;
-//
-//   runStepCreated: (runStep: RunStep) => void;
-//   runStepDelta: (delta: RunStepDelta, snapshot: Runs.RunStep) => void;
-//   runStepDone: (runStep: Runs.RunStep, snapshot: Runs.RunStep) => void;
-//
-//   toolCallCreated: (toolCall: ToolCall) => void;
-//   toolCallDelta: (delta: ToolCallDelta, snapshot: ToolCall) => void;
-//   toolCallDone: (toolCall: ToolCall) => void;
-//
-//   textCreated: (content: Text) => void;
-//   textDelta: (delta: TextDelta, snapshot: Text) => void;
-//   textDone: (content: Text, snapshot: Message) => void;
-//
-//   //No created or delta as this is not streamed
-//   imageFileDone: (content: ImageFile, snapshot: Message) => void;
-//
-//   end: () => void;
-//
-//   event: (event: AssistantStreamEvent) => void;
-// }
-//
-// export type ThreadCreateAndRunParamsBaseStream = Omit<ThreadCreateAndRunParamsBase, 'stream'> & {
-//   stream?: true;
-// };
-//
-// export type RunCreateParamsBaseStream = Omit<RunCreateParamsBase, 'stream'> & {
-//   stream?: true;
-// };
-//
-// export type RunSubmitToolOutputsParamsStream = Omit<RunSubmitToolOutputsParamsBase, 'stream'> & {
-//   stream?: true;
-// };
-//
-// export class AssistantStream
-//   extends AbstractAssistantStreamRunner<AssistantStreamEvents>
-//   implements AsyncIterable<AssistantStreamEvent>
-// {
-//   //Track all events in a single list for reference
-//   #events: AssistantStreamEvent[] = [];
-//
-//   //Used to accumulate deltas
-//   //We are accumulating many types so the value here is not strict
-//   #runStepSnapshots: { [id: string]: Runs.RunStep } = {};
-//   #messageSnapshots: { [id: string]: Message } = {};
-//   #messageSnapshot: Message | undefined;
-//   #finalRun: Run | undefined;
-//   #currentContentIndex: number | undefined;
-//   #currentContent: MessageContent | undefined;
-//   #currentToolCallIndex: number | undefined;
-//   #currentToolCall: ToolCall | undefined;
-//
-//   //For current snapshot methods
-//   #currentEvent: AssistantStreamEvent | undefined;
-//   #currentRunSnapshot: Run | undefined;
-//   #currentRunStepSnapshot: Runs.RunStep | undefined;
-//
-//   [Symbol.asyncIterator](): AsyncIterator<AssistantStreamEvent> {
-//     const pushQueue: AssistantStreamEvent[] = [];
-//     const readQueue: {
-//       resolve: (chunk: AssistantStreamEvent | undefined) => void;
-//       reject: (err: unknown) => void;
-//     }[] = [];
-//     let done = false;
-//
-//     //Catch all for passing along all events
-//     this.on('event', (event) => {
-//       const reader = readQueue.shift();
-//       if (reader) {
-//         reader.resolve(event);
-//       } else {
-//         pushQueue.push(event);
-//       }
-//     });
-//
-//     this.on('end', () => {
-//       done = true;
-//       for (const reader of readQueue) {
-//         reader.resolve(undefined);
-//       }
-//       readQueue.length = 0;
-//     });
-//
-//     this.on('abort', (err) => {
-//       done = true;
-//       for (const reader of readQueue) {
-//         reader.reject(err);
-//       }
-//       readQueue.length = 0;
-//     });
-//
-//     this.on('error', (err) => {
-//       done = true;
-//       for (const reader of readQueue) {
-//         reader.reject(err);
-//       }
-//       readQueue.length = 0;
-//     });
-//
-//     return {
-//       next: async (): Promise<IteratorResult<AssistantStreamEvent>> => {
-//         if (!pushQueue.length) {
-//           if (done) {
-//             return { value: undefined, done: true };
-//           }
-//           return new Promise<AssistantStreamEvent | undefined>((resolve, reject) =>
-//             readQueue.push({ resolve, reject }),
-//           ).then((chunk) => (chunk ? { value: chunk, done: false } : { value: undefined, done: true }));
-//         }
-//         const chunk = pushQueue.shift()!;
-//         return { value: chunk, done: false };
-//       },
-//       return: async () => {
-//         this.abort();
-//         return { value: undefined, done: true };
-//       },
-//     };
-//   }
-//
-//   static fromReadableStream(stream: ReadableStream): AssistantStream {
-//     const runner = new AssistantStream();
-//     runner._run(() => runner._fromReadableStream(stream));
-//     return runner;
-//   }
-//
-//   protected async _fromReadableStream(
-//     readableStream: ReadableStream,
-//     options?: Core.RequestOptions,
-//   ): Promise<Run> {
-//     const signal = options?.signal;
-//     if (signal) {
-//       if (signal.aborted) this.controller.abort();
-//       signal.addEventListener('abort', () => this.controller.abort());
-//     }
-//     this._connected();
-//     const stream = Stream.fromReadableStream<AssistantStreamEvent>(readableStream, this.controller);
-//     for await (const event of stream) {
-//       this.#addEvent(event);
-//     }
-//     if (stream.controller.signal?.aborted) {
-//       throw new APIUserAbortError();
-//     }
-//     return this._addRun(this.#endRequest());
-//   }
-//
-//   toReadableStream(): ReadableStream {
-//     const stream = new Stream(this[Symbol.asyncIterator].bind(this), this.controller);
-//     return stream.toReadableStream();
-//   }
-//
-//   static createToolAssistantStream(
-//     threadId: string,
-//     runId: string,
-//     runs: Runs,
-//     body: RunSubmitToolOutputsParamsStream,
-//     options: RequestOptions | undefined,
-//   ) {
-//     const runner = new AssistantStream();
-//     runner._run(() =>
-//       runner._runToolAssistantStream(threadId, runId, runs, body, {
-//         ...options,
-//         headers: { ...options?.headers, 'X-Stainless-Helper-Method': 'stream' },
-//       }),
-//     );
-//     return runner;
-//   }
-//
-//   protected override async _createToolAssistantStream(
-//     run: Runs,
-//     threadId: string,
-//     runId: string,
-//     params: RunSubmitToolOutputsParamsStream,
-//     options?: Core.RequestOptions,
-//   ): Promise<Run> {
-//     const signal = options?.signal;
-//     if (signal) {
-//       if (signal.aborted) this.controller.abort();
-//       signal.addEventListener('abort', () => this.controller.abort());
-//     }
-//
-//     const body: RunSubmitToolOutputsParamsStreaming = { ...params, stream: true };
-//     const stream = await run.submitToolOutputs(threadId, runId, body, {
-//       ...options,
-//       signal: this.controller.signal,
-//     });
-//
-//     this._connected();
-//
-//     for await (const event of stream) {
-//       this.#addEvent(event);
-//     }
-//     if (stream.controller.signal?.aborted) {
-//       throw new APIUserAbortError();
-//     }
-//
-//     return this._addRun(this.#endRequest());
-//   }
-//
-//   static createThreadAssistantStream(
-//     body: ThreadCreateAndRunParamsBaseStream,
-//     thread: Threads,
-//     options?: RequestOptions,
-//   ) {
-//     const runner = new AssistantStream();
-//     runner._run(() =>
-//       runner._threadAssistantStream(body, thread, {
-//         ...options,
-//         headers: { ...options?.headers, 'X-Stainless-Helper-Method': 'stream' },
-//       }),
-//     );
-//     return runner;
-//   }
-//
-//   static createAssistantStream(
-//     threadId: string,
-//     runs: Runs,
-//     params: RunCreateParamsBaseStream,
-//     options?: RequestOptions,
-//   ) {
-//     const runner = new AssistantStream();
-//     runner._run(() =>
-//       runner._runAssistantStream(threadId, runs, params, {
-//         ...options,
-//         headers: { ...options?.headers, 'X-Stainless-Helper-Method': 'stream' },
-//       }),
-//     );
-//     return runner;
-//   }
-//
-//   currentEvent(): AssistantStreamEvent | undefined {
-//     return this.#currentEvent;
-//   }
-//
-//   currentRun(): Run | undefined {
-//     return this.#currentRunSnapshot;
-//   }
-//
-//   currentMessageSnapshot(): Message | undefined {
-//     return this.#messageSnapshot;
-//   }
-//
-//   currentRunStepSnapshot(): Runs.RunStep | undefined {
-//     return this.#currentRunStepSnapshot;
-//   }
-//
-//   async finalRunSteps(): Promise<Runs.RunStep[]> {
-//     await this.done();
-//
-//     return Object.values(this.#runStepSnapshots);
-//   }
-//
-//   async finalMessages(): Promise<Message[]> {
-//     await this.done();
-//
-//     return Object.values(this.#messageSnapshots);
-//   }
-//
-//   async finalRun(): Promise<Run> {
-//     await this.done();
-//     if (!this.#finalRun) throw Error('Final run was not received.');
-//
-//     return this.#finalRun;
-//   }
-//
-//   protected override async _createThreadAssistantStream(
-//     thread: Threads,
-//     params: ThreadCreateAndRunParamsBase,
-//     options?: Core.RequestOptions,
-//   ): Promise<Run> {
-//     const signal = options?.signal;
-//     if (signal) {
-//       if (signal.aborted) this.controller.abort();
-//       signal.addEventListener('abort', () => this.controller.abort());
-//     }
-//
-//     const body: RunCreateParamsStreaming = { ...params, stream: true };
-//     const stream = await thread.createAndRun(body, { ...options, signal: this.controller.signal });
-//
-//     this._connected();
-//
-//     for await (const event of stream) {
-//       this.#addEvent(event);
-//     }
-//     if (stream.controller.signal?.aborted) {
-//       throw new APIUserAbortError();
-//     }
-//
-//     return this._addRun(this.#endRequest());
-//   }
-//
-//   protected override async _createAssistantStream(
-//     run: Runs,
-//     threadId: string,
-//     params: RunCreateParamsBase,
-//     options?: Core.RequestOptions,
-//   ): Promise<Run> {
-//     const signal = options?.signal;
-//     if (signal) {
-//       if (signal.aborted) this.controller.abort();
-//       signal.addEventListener('abort', () => this.controller.abort());
-//     }
-//
-//     const body: RunCreateParamsStreaming = { ...params, stream: true };
-//     const stream = await run.create(threadId, body, { ...options, signal: this.controller.signal });
-//
-//     this._connected();
-//
-//     for await (const event of stream) {
-//       this.#addEvent(event);
-//     }
-//     if (stream.controller.signal?.aborted) {
-//       throw new APIUserAbortError();
-//     }
-//
-//     return this._addRun(this.#endRequest());
-//   }
-//
-//   #addEvent(event: AssistantStreamEvent) {
-//     if (this.ended) return;
-//
-//     this.#currentEvent = event;
-//
-//     this.#handleEvent(event);
-//
-//     switch (event.event) {
-//       case 'thread.created':
-//         //No action on this event.
-//         break;
-//
-//       case 'thread.run.created':
-//       case 'thread.run.queued':
-//       case 'thread.run.in_progress':
-//       case 'thread.run.requires_action':
-//       case 'thread.run.completed':
-//       case 'thread.run.failed':
-//       case 'thread.run.cancelling':
-//       case 'thread.run.cancelled':
-//       case 'thread.run.expired':
-//         this.#handleRun(event);
-//         break;
-//
-//       case 'thread.run.step.created':
-//       case 'thread.run.step.in_progress':
-//       case 'thread.run.step.delta':
-//       case 'thread.run.step.completed':
-//       case 'thread.run.step.failed':
-//       case 'thread.run.step.cancelled':
-//       case 'thread.run.step.expired':
-//         this.#handleRunStep(event);
-//         break;
-//
-//       case 'thread.message.created':
-//       case 'thread.message.in_progress':
-//       case 'thread.message.delta':
-//       case 'thread.message.completed':
-//       case 'thread.message.incomplete':
-//         this.#handleMessage(event);
-//         break;
-//
-//       case 'error':
-//         //This is included for completeness, but errors are processed in the SSE event processing so this should not occur
-//         throw new Error(
-//           'Encountered an error event in event processing - errors should be processed earlier',
-//         );
-//     }
-//   }
-//
-//   #endRequest(): Run {
-//     if (this.ended) {
-//       throw new OpenAIError(`stream has ended, this shouldn't happen`);
-//     }
-//
-//     if (!this.#finalRun) throw Error('Final run has not been received');
-//
-//     return this.#finalRun;
-//   }
-//
-//   #handleMessage(event: MessageStreamEvent) {
-//     const [accumulatedMessage, newContent] = this.#accumulateMessage(event, this.#messageSnapshot);
-//     this.#messageSnapshot = accumulatedMessage;
-//     this.#messageSnapshots[accumulatedMessage.id] = accumulatedMessage;
-//
-//     for (const content of newContent) {
-//       const snapshotContent = accumulatedMessage.content[content.index];
-//       if (snapshotContent?.type == 'text') {
-//         this._emit('textCreated', snapshotContent.text);
-//       }
-//     }
-//
-//     switch (event.event) {
-//       case 'thread.message.created':
-//         this._emit('messageCreated', event.data);
-//         break;
-//
-//       case 'thread.message.in_progress':
-//         break;
-//
-//       case 'thread.message.delta':
-//         this._emit('messageDelta', event.data.delta, accumulatedMessage);
-//
-//         if (event.data.delta.content) {
-//           for (const content of event.data.delta.content) {
-//             //If it is text delta, emit a text delta event
-//             if (content.type == 'text' && content.text) {
-//               let textDelta = content.text;
-//               let snapshot = accumulatedMessage.content[content.index];
-//               if (snapshot && snapshot.type == 'text') {
-//                 this._emit('textDelta', textDelta, snapshot.text);
-//               } else {
-//                 throw Error('The snapshot associated with this text delta is not text or missing');
-//               }
-//             }
-//
-//             if (content.index != this.#currentContentIndex) {
-//               //See if we have in progress content
-//               if (this.#currentContent) {
-//                 switch (this.#currentContent.type) {
-//                   case 'text':
-//                     this._emit('textDone', this.#currentContent.text, this.#messageSnapshot);
-//                     break;
-//                   case 'image_file':
-//                     this._emit('imageFileDone', this.#currentContent.image_file, this.#messageSnapshot);
-//                     break;
-//                 }
-//               }
-//
-//               this.#currentContentIndex = content.index;
-//             }
-//
-//             this.#currentContent = accumulatedMessage.content[content.index];
-//           }
-//         }
-//
-//         break;
-//
-//       case 'thread.message.completed':
-//       case 'thread.message.incomplete':
-//         //We emit the latest content we were working on on completion (including incomplete)
-//         if (this.#currentContentIndex !== undefined) {
-//           const currentContent = event.data.content[this.#currentContentIndex];
-//           if (currentContent) {
-//             switch (currentContent.type) {
-//               case 'image_file':
-//                 this._emit('imageFileDone', currentContent.image_file, this.#messageSnapshot);
-//                 break;
-//               case 'text':
-//                 this._emit('textDone', currentContent.text, this.#messageSnapshot);
-//                 break;
-//             }
-//           }
-//         }
-//
-//         if (this.#messageSnapshot) {
-//           this._emit('messageDone', event.data);
-//         }
-//
-//         this.#messageSnapshot = undefined;
-//     }
-//   }
-//
-//   #handleRunStep(event: RunStepStreamEvent) {
-//     const accumulatedRunStep = this.#accumulateRunStep(event);
-//     this.#currentRunStepSnapshot = accumulatedRunStep;
-//
-//     switch (event.event) {
-//       case 'thread.run.step.created':
-//         this._emit('runStepCreated', event.data);
-//         break;
-//       case 'thread.run.step.delta':
-//         const delta = event.data.delta;
-//         if (
-//           delta.step_details &&
-//           delta.step_details.type == 'tool_calls' &&
-//           delta.step_details.tool_calls &&
-//           accumulatedRunStep.step_details.type == 'tool_calls'
-//         ) {
-//           for (const toolCall of delta.step_details.tool_calls) {
-//             if (toolCall.index == this.#currentToolCallIndex) {
-//               this._emit(
-//                 'toolCallDelta',
-//                 toolCall,
-//                 accumulatedRunStep.step_details.tool_calls[toolCall.index] as ToolCall,
-//               );
-//             } else {
-//               if (this.#currentToolCall) {
-//                 this._emit('toolCallDone', this.#currentToolCall);
-//               }
-//
-//               this.#currentToolCallIndex = toolCall.index;
-//               this.#currentToolCall = accumulatedRunStep.step_details.tool_calls[toolCall.index];
-//               if (this.#currentToolCall) this._emit('toolCallCreated', this.#currentToolCall);
-//             }
-//           }
-//         }
-//
-//         this._emit('runStepDelta', event.data.delta, accumulatedRunStep);
-//         break;
-//       case 'thread.run.step.completed':
-//       case 'thread.run.step.failed':
-//       case 'thread.run.step.cancelled':
-//       case 'thread.run.step.expired':
-//         this.#currentRunStepSnapshot = undefined;
-//         const details = event.data.step_details;
-//         if (details.type == 'tool_calls') {
-//           if (this.#currentToolCall) {
-//             this._emit('toolCallDone', this.#currentToolCall as ToolCall);
-//             this.#currentToolCall = undefined;
-//           }
-//         }
-//         this._emit('runStepDone', event.data, accumulatedRunStep);
-//         break;
-//       case 'thread.run.step.in_progress':
-//         break;
-//     }
-//   }
-//
-//   #handleEvent(event: AssistantStreamEvent) {
-//     this.#events.push(event);
-//     this._emit('event', event);
-//   }
-//
-//   #accumulateRunStep(event: RunStepStreamEvent): Runs.RunStep {
-//     switch (event.event) {
-//       case 'thread.run.step.created':
-//         this.#runStepSnapshots[event.data.id] = event.data;
-//         return event.data;
-//
-//       case 'thread.run.step.delta':
-//         let snapshot = this.#runStepSnapshots[event.data.id] as Runs.RunStep;
-//         if (!snapshot) {
-//           throw Error('Received a RunStepDelta before creation of a snapshot');
-//         }
-//
-//         let data = event.data;
-//
-//         if (data.delta) {
-//           const accumulated = AssistantStream.accumulateDelta(snapshot, data.delta) as Runs.RunStep;
-//           this.#runStepSnapshots[event.data.id] = accumulated;
-//         }
-//
-//         return this.#runStepSnapshots[event.data.id] as Runs.RunStep;
-//
-//       case 'thread.run.step.completed':
-//       case 'thread.run.step.failed':
-//       case 'thread.run.step.cancelled':
-//       case 'thread.run.step.expired':
-//       case 'thread.run.step.in_progress':
-//         this.#runStepSnapshots[event.data.id] = event.data;
-//         break;
-//     }
-//
-//     if (this.#runStepSnapshots[event.data.id]) return this.#runStepSnapshots[event.data.id] as Runs.RunStep;
-//     throw new Error('No snapshot available');
-//   }
-//
-//   #accumulateMessage(
-//     event: AssistantStreamEvent,
-//     snapshot: Message | undefined,
-//   ): [Message, MessageContentDelta[]] {
-//     let newContent: MessageContentDelta[] = [];
-//
-//     switch (event.event) {
-//       case 'thread.message.created':
-//         //On creation the snapshot is just the initial message
-//         return [event.data, newContent];
-//
-//       case 'thread.message.delta':
-//         if (!snapshot) {
-//           throw Error(
-//             'Received a delta with no existing snapshot (there should be one from message creation)',
-//           );
-//         }
-//
-//         let data = event.data;
-//
-//         //If this delta does not have content, nothing to process
-//         if (data.delta.content) {
-//           for (const contentElement of data.delta.content) {
-//             if (contentElement.index in snapshot.content) {
-//               let currentContent = snapshot.content[contentElement.index];
-//               snapshot.content[contentElement.index] = this.#accumulateContent(
-//                 contentElement,
-//                 currentContent,
-//               );
-//             } else {
-//               snapshot.content[contentElement.index] = contentElement as MessageContent;
-//               // This is a new element
-//               newContent.push(contentElement);
-//             }
-//           }
-//         }
-//
-//         return [snapshot, newContent];
-//
-//       case 'thread.message.in_progress':
-//       case 'thread.message.completed':
-//       case 'thread.message.incomplete':
-//         //No changes on other thread events
-//         if (snapshot) {
-//           return [snapshot, newContent];
-//         } else {
-//           throw Error('Received thread message event with no existing snapshot');
-//         }
-//     }
-//     throw Error('Tried to accumulate a non-message event');
-//   }
-//
-//   #accumulateContent(
-//     contentElement: MessageContentDelta,
-//     currentContent: MessageContent | undefined,
-//   ): TextContentBlock | ImageFileContentBlock {
-//     return AssistantStream.accumulateDelta(currentContent as unknown as Record<any, any>, contentElement) as
-//       | TextContentBlock
-//       | ImageFileContentBlock;
-//   }
-//
-//   static accumulateDelta(acc: Record<string, any>, delta: Record<string, any>): Record<string, any> {
-//     for (const [key, deltaValue] of Object.entries(delta)) {
-//       if (!acc.hasOwnProperty(key)) {
-//         acc[key] = deltaValue;
-//         continue;
-//       }
-//
-//       let accValue = acc[key];
-//       if (accValue === null || accValue === undefined) {
-//         acc[key] = deltaValue;
-//         continue;
-//       }
-//
-//       // We don't accumulate these special properties
-//       if (key === 'index' || key === 'type') {
-//         acc[key] = deltaValue;
-//         continue;
-//       }
-//
-//       // Type-specific accumulation logic
-//       if (typeof accValue === 'string' && typeof deltaValue === 'string') {
-//         accValue += deltaValue;
-//       } else if (typeof accValue === 'number' && typeof deltaValue === 'number') {
-//         accValue += deltaValue;
-//       } else if (Core.isObj(accValue) && Core.isObj(deltaValue)) {
-//         accValue = this.accumulateDelta(accValue as Record<string, any>, deltaValue as Record<string, any>);
-//       } else if (Array.isArray(accValue) && Array.isArray(deltaValue)) {
-//         if (accValue.every((x) => typeof x === 'string' || typeof x === 'number')) {
-//           accValue.push(...deltaValue); // Use spread syntax for efficient addition
-//           continue;
-//         }
-//       } else {
-//         throw Error(`Unhandled record type: ${key}, deltaValue: ${deltaValue}, accValue: ${accValue}`);
-//       }
-//       acc[key] = accValue;
-//     }
-//
-//     return acc;
-//   }
-//
-//   #handleRun(event: RunStreamEvent) {
-//     this.#currentRunSnapshot = event.data;
-//     switch (event.event) {
-//       case 'thread.run.created':
-//         break;
-//       case 'thread.run.queued':
-//         break;
-//       case 'thread.run.in_progress':
-//         break;
-//       case 'thread.run.requires_action':
-//       case 'thread.run.cancelled':
-//       case 'thread.run.failed':
-//       case 'thread.run.completed':
-//       case 'thread.run.expired':
-//         this.#finalRun = event.data;
-//         if (this.#currentToolCall) {
-//           this._emit('toolCallDone', this.#currentToolCall);
-//           this.#currentToolCall = undefined;
-//         }
-//         break;
-//       case 'thread.run.cancelling':
-//         break;
-//     }
-//   }
-// }
\ No newline at end of file
+/// Consumes the raw events of an assistant stream and yields the [`AssistantStream`] events,
+/// keeping snapshots of the messages, run steps and tool calls by applying their deltas.
+///
+/// Errors, including [`Error::UserAbort`], end the stream as its last item.
+pub struct AssistantStreamRunner {
+    events: Pin<Box<dyn Stream<Item=Result<AssistantStreamEvent, Error>>>>,
+    queue: VecDeque<AssistantStream>,
+    connected: bool,
+    ended: bool,
+
+    //Used to accumulate deltas, in the order they were created
+    run_step_snapshots: Vec<RunStep>,
+    message_snapshots: Vec<Message>,
+    message_snapshot: Option<Message>,
+    final_run: Option<Run>,
+    current_content_index: Option<usize>,
+    current_content: Option<MessageContent>,
+    current_tool_call_index: Option<usize>,
+    current_tool_call: Option<ToolCall>,
+
+    //For current snapshot methods
+    current_event: Option<AssistantStreamEvent>,
+    current_run_snapshot: Option<Run>,
+    current_run_step_snapshot: Option<RunStep>,
+}
+
+impl<Req: Default + Clone + Serialize + 'static> APIFuture<Req, (), AssistantStreamEvent> {
+    /// Sends the request and runs its events through an [`AssistantStreamRunner`].
+    pub fn into_assistant_stream(self) -> AssistantStreamRunner {
+        AssistantStreamRunner::new(self.into_stream())
+    }
+}
+
+impl AssistantStreamRunner {
+    pub fn new(events: impl Stream<Item=Result<AssistantStreamEvent, Error>> + 'static) -> Self {
+        AssistantStreamRunner {
+            events: Box::pin(events),
+            queue: VecDeque::new(),
+            connected: false,
+            ended: false,
+            run_step_snapshots: vec![],
+            message_snapshots: vec![],
+            message_snapshot: None,
+            final_run: None,
+            current_content_index: None,
+            current_content: None,
+            current_tool_call_index: None,
+            current_tool_call: None,
+            current_event: None,
+            current_run_snapshot: None,
+            current_run_step_snapshot: None,
+        }
+    }
+
+    /// The last raw event received.
+    pub fn current_event(&self) -> Option<&AssistantStreamEvent> {
+        self.current_event.as_ref()
+    }
+
+    /// The last state of the run received.
+    pub fn current_run(&self) -> Option<&Run> {
+        self.current_run_snapshot.as_ref()
+    }
+
+    /// The message being streamed, if any.
+    pub fn current_message_snapshot(&self) -> Option<&Message> {
+        self.message_snapshot.as_ref()
+    }
+
+    /// The run step being streamed, if any.
+    pub fn current_run_step_snapshot(&self) -> Option<&RunStep> {
+        self.current_run_step_snapshot.as_ref()
+    }
+
+    /// Consumes the rest of the stream, failing with its error if any.
+    pub async fn done(&mut self) -> Result<(), Error> {
+        while let Some(event) = self.next().await {
+            event?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the end of the stream and returns every run step, in creation order.
+    pub async fn final_run_steps(&mut self) -> Result<Vec<RunStep>, Error> {
+        self.done().await?;
+        Ok(self.run_step_snapshots.clone())
+    }
+
+    /// Waits for the end of the stream and returns every message, in creation order.
+    pub async fn final_messages(&mut self) -> Result<Vec<Message>, Error> {
+        self.done().await?;
+        Ok(self.message_snapshots.clone())
+    }
+
+    /// Waits for the end of the stream and returns the run in its last state.
+    pub async fn final_run(&mut self) -> Result<Run, Error> {
+        self.done().await?;
+        self.final_run.clone().ok_or_else(|| Error::OpenAI("Final run was not received.".to_string()))
+    }
+
+    fn emit(&mut self, event: AssistantStream) {
+        self.queue.push_back(event);
+    }
+
+    fn add_event(&mut self, event: AssistantStreamEvent) -> Result<(), Error> {
+        self.current_event = Some(event.clone());
+        self.emit(AssistantStream::Event(event.clone()));
+
+        match event {
+            AssistantStreamEvent::ThreadRunCreated(run)
+            | AssistantStreamEvent::ThreadRunQueued(run)
+            | AssistantStreamEvent::ThreadRunInProgress(run)
+            | AssistantStreamEvent::ThreadRunCancelling(run) => {
+                self.current_run_snapshot = Some(run);
+            }
+            AssistantStreamEvent::ThreadRunRequiresAction(run)
+            | AssistantStreamEvent::ThreadRunCompleted(run)
+            | AssistantStreamEvent::ThreadRunIncomplete(run)
+            | AssistantStreamEvent::ThreadRunFailed(run)
+            | AssistantStreamEvent::ThreadRunCancelled(run)
+            | AssistantStreamEvent::ThreadRunExpired(run) => {
+                self.current_run_snapshot = Some(run.clone());
+                self.final_run = Some(run);
+                if let Some(tool_call) = self.current_tool_call.take() {
+                    self.emit(AssistantStream::ToolCallDone(tool_call));
+                }
+            }
+
+            AssistantStreamEvent::ThreadRunStepCreated(run_step) => {
+                self.set_run_step_snapshot(run_step.clone());
+                self.current_run_step_snapshot = Some(run_step.clone());
+                self.emit(AssistantStream::RunStepCreated(run_step));
+            }
+            AssistantStreamEvent::ThreadRunStepInProgress(run_step) => {
+                self.set_run_step_snapshot(run_step.clone());
+                self.current_run_step_snapshot = Some(run_step);
+            }
+            AssistantStreamEvent::ThreadRunStepDelta(event) => {
+                self.handle_run_step_delta(event.id, event.delta)?;
+            }
+            AssistantStreamEvent::ThreadRunStepCompleted(run_step)
+            | AssistantStreamEvent::ThreadRunStepFailed(run_step)
+            | AssistantStreamEvent::ThreadRunStepCancelled(run_step)
+            | AssistantStreamEvent::ThreadRunStepExpired(run_step) => {
+                self.set_run_step_snapshot(run_step.clone());
+                self.current_run_step_snapshot = None;
+                if let run_step::StepDetails::ToolCallsStepDetails(_) = run_step.step_details {
+                    if let Some(tool_call) = self.current_tool_call.take() {
+                        self.emit(AssistantStream::ToolCallDone(tool_call));
+                    }
+                }
+                self.current_tool_call_index = None;
+                self.emit(AssistantStream::RunStepDone { run_step: run_step.clone(), snapshot: run_step });
+            }
+
+            AssistantStreamEvent::ThreadMessageCreated(message) => {
+                //On creation the snapshot is just the initial message
+                self.set_message_snapshot(message.clone());
+                self.emit(AssistantStream::MessageCreated(message));
+            }
+            AssistantStreamEvent::ThreadMessageInProgress(_) => {
+                if self.message_snapshot.is_none() {
+                    return Err(Error::OpenAI("Received thread message event with no existing snapshot".to_string()));
+                }
+            }
+            AssistantStreamEvent::ThreadMessageDelta(event) => {
+                self.handle_message_delta(event.delta)?;
+            }
+            AssistantStreamEvent::ThreadMessageCompleted(message)
+            | AssistantStreamEvent::ThreadMessageIncomplete(message) => {
+                //We emit the latest content we were working on on completion (including incomplete)
+                if let Some(content) = self.current_content_index.and_then(|index| message.content.get(index)) {
+                    self.emit_content_done(content.clone(), &message);
+                }
+                if self.message_snapshot.take().is_some() {
+                    self.emit(AssistantStream::MessageDone(message.clone()));
+                }
+                self.set_message_snapshot(message);
+                self.message_snapshot = None;
+                self.current_content_index = None;
+                self.current_content = None;
+            }
+
+            AssistantStreamEvent::ErrorEvent(error) => {
+                //Errors are processed by `APIFuture::into_stream`, this should not occur
+                return Err(Error::OpenAI(error.message));
+            }
+            AssistantStreamEvent::ThreadCreated(_) | AssistantStreamEvent::Unknown { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    fn handle_message_delta(&mut self, delta: MessageDelta) -> Result<(), Error> {
+        let mut snapshot = self.message_snapshot.take().ok_or_else(|| {
+            Error::OpenAI("Received a delta with no existing snapshot (there should be one from message creation)".to_string())
+        })?;
+        let new_content = accumulate_message(&mut snapshot, &delta)?;
+        self.set_message_snapshot(snapshot.clone());
+
+        for index in new_content {
+            if let Some(MessageContent::Text { text }) = snapshot.content.get(index) {
+                self.emit(AssistantStream::TextCreated(text.clone()));
+            }
+        }
+
+        self.emit(AssistantStream::MessageDelta { delta: delta.clone(), snapshot: snapshot.clone() });
+
+        for content in delta.content.into_iter().flatten() {
+            let index = content_index(&content);
+
+            //If it is text delta, emit a text delta event
+            if let MessageContentDelta::TextDeltaBlock(TextDeltaBlock { text: Some(text_delta), .. }) = content {
+                match snapshot.content.get(index) {
+                    Some(MessageContent::Text { text }) => {
+                        self.emit(AssistantStream::TextDelta { delta: text_delta, snapshot: text.clone() });
+                    }
+                    _ => return Err(Error::OpenAI("The snapshot associated with this text delta is not text or missing".to_string())),
+                }
+            }
+
+            if Some(index) != self.current_content_index {
+                //See if we have in progress content
+                if let Some(current_content) = self.current_content.take() {
+                    self.emit_content_done(current_content, &snapshot);
+                }
+                self.current_content_index = Some(index);
+            }
+
+            self.current_content = snapshot.content.get(index).cloned();
+        }
+
+        Ok(())
+    }
+
+    fn handle_run_step_delta(&mut self, id: String, delta: RunStepDelta) -> Result<(), Error> {
+        let position = self.run_step_snapshots.iter()
+            .position(|run_step| run_step.id == id)
+            .ok_or_else(|| Error::OpenAI("Received a RunStepDelta before creation of a snapshot".to_string()))?;
+
+        let mut accumulated = serde_json::to_value(&self.run_step_snapshots[position])?;
+        if let (Value::Object(accumulated), Value::Object(delta)) = (&mut accumulated, serde_json::to_value(&delta)?) {
+            accumulate_delta(accumulated, delta)?;
+        }
+        let snapshot: RunStep = serde_json::from_value(accumulated)?;
+        self.run_step_snapshots[position] = snapshot.clone();
+        self.current_run_step_snapshot = Some(snapshot.clone());
+
+        if let (
+            Some(run_step_delta::StepDetails::ToolCalls(ToolCallDeltaObject { tool_calls: Some(tool_calls) })),
+            run_step::StepDetails::ToolCallsStepDetails(details),
+        ) = (&delta.step_details, &snapshot.step_details) {
+            for tool_call in tool_calls {
+                let index = tool_call_index(tool_call);
+                let tool_call_snapshot = details.tool_calls.get(index).cloned();
+
+                if Some(index) == self.current_tool_call_index {
+                    if let Some(tool_call_snapshot) = tool_call_snapshot {
+                        self.current_tool_call = Some(tool_call_snapshot.clone());
+                        self.emit(AssistantStream::ToolCallDelta { delta: tool_call.clone(), snapshot: tool_call_snapshot });
+                    }
+                } else {
+                    if let Some(current_tool_call) = self.current_tool_call.take() {
+                        self.emit(AssistantStream::ToolCallDone(current_tool_call));
+                    }
+
+                    self.current_tool_call_index = Some(index);
+                    self.current_tool_call = tool_call_snapshot.clone();
+                    if let Some(tool_call_snapshot) = tool_call_snapshot {
+                        self.emit(AssistantStream::ToolCallCreated(tool_call_snapshot));
+                    }
+                }
+            }
+        }
+
+        self.emit(AssistantStream::RunStepDelta { delta, snapshot });
+        Ok(())
+    }
+
+    fn emit_content_done(&mut self, content: MessageContent, snapshot: &Message) {
+        match content {
+            MessageContent::Text { text } => {
+                self.emit(AssistantStream::TextDone { content: text, snapshot: snapshot.clone() });
+            }
+            MessageContent::ImageFile { image_file } => {
+                self.emit(AssistantStream::ImageFileDone { content: image_file, snapshot: snapshot.clone() });
+            }
+            MessageContent::ImageURL { .. } => {}
+        }
+    }
+
+    fn set_message_snapshot(&mut self, message: Message) {
+        match self.message_snapshots.iter_mut().find(|snapshot| snapshot.id == message.id) {
+            Some(snapshot) => *snapshot = message.clone(),
+            None => self.message_snapshots.push(message.clone()),
+        }
+        self.message_snapshot = Some(message);
+    }
+
+    fn set_run_step_snapshot(&mut self, run_step: RunStep) {
+        match self.run_step_snapshots.iter_mut().find(|snapshot| snapshot.id == run_step.id) {
+            Some(snapshot) => *snapshot = run_step,
+            None => self.run_step_snapshots.push(run_step),
+        }
+    }
+
+    fn end_request(&mut self) -> Result<Run, Error> {
+        self.final_run.clone().ok_or_else(|| Error::OpenAI("Final run has not been received".to_string()))
+    }
+}
+
+impl Stream for AssistantStreamRunner {
+    type Item = Result<AssistantStream, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(event) = this.queue.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if this.ended {
+                return Poll::Ready(None);
+            }
+
+            let event = futures::ready!(this.events.as_mut().poll_next(cx));
+            if !this.connected && !matches!(event, Some(Err(_))) {
+                this.connected = true;
+                this.emit(AssistantStream::Connect);
+            }
+
+            let result = match event {
+                Some(Ok(event)) => this.add_event(event),
+                Some(Err(error)) => Err(error),
+                None => this.end_request().map(|run| {
+                    this.ended = true;
+                    this.emit(AssistantStream::Run(run));
+                    this.emit(AssistantStream::End);
+                }),
+            };
+            if let Err(error) = result {
+                this.ended = true;
+                this.queue.clear();
+                return Poll::Ready(Some(Err(error)));
+            }
+        }
+    }
+}
+
+fn content_index(content: &MessageContentDelta) -> usize {
+    let index = match content {
+        MessageContentDelta::ImageFileDeltaBlock(block) => block.index,
+        MessageContentDelta::TextDeltaBlock(block) => block.index,
+        MessageContentDelta::ImageURLDeltaBlock(block) => block.index,
+    };
+    index as usize
+}
+
+fn tool_call_index(tool_call: &ToolCallDelta) -> usize {
+    let index = match tool_call {
+        ToolCallDelta::CodeInterpreter(tool_call) => tool_call.index,
+        ToolCallDelta::FileSearch(tool_call) => tool_call.index,
+        ToolCallDelta::Function(tool_call) => tool_call.index,
+    };
+    index as usize
+}
+
+/// Applies the content of a message delta to the snapshot, returning the indexes of the new content.
+fn accumulate_message(snapshot: &mut Message, delta: &MessageDelta) -> Result<Vec<usize>, Error> {
+    let mut new_content = vec![];
+
+    for element in delta.content.iter().flatten() {
+        let index = content_index(element);
+        let Value::Object(element) = serde_json::to_value(element)? else {
+            continue;
+        };
+
+        match snapshot.content.get_mut(index) {
+            Some(content) => {
+                let mut accumulated = serde_json::to_value(&*content)?;
+                if let Value::Object(accumulated) = &mut accumulated {
+                    accumulate_delta(accumulated, element)?;
+                }
+                *content = serde_json::from_value(accumulated)?;
+            }
+            None => {
+                // This is a new element
+                snapshot.content.push(serde_json::from_value(Value::Object(element))?);
+                new_content.push(snapshot.content.len() - 1);
+            }
+        }
+    }
+
+    Ok(new_content)
+}
+
+/// Merges a delta into a snapshot: strings and numbers are appended, objects are merged and
+/// the entries of object arrays are merged with the entry at their `index`.
+fn accumulate_delta(acc: &mut Map<String, Value>, delta: Map<String, Value>) -> Result<(), Error> {
+    for (key, delta_value) in delta {
+        if delta_value.is_null() {
+            continue;
+        }
+        if acc.get(&key).is_none_or(Value::is_null) {
+            acc.insert(key, delta_value);
+            continue;
+        }
+
+        // We don't accumulate these special properties
+        if key == "index" || key == "type" {
+            acc.insert(key, delta_value);
+            continue;
+        }
+
+        let Some(acc_value) = acc.get_mut(&key) else {
+            continue;
+        };
+        match (acc_value, delta_value) {
+            (Value::String(acc_value), Value::String(delta_value)) => acc_value.push_str(&delta_value),
+            (Value::Number(acc_value), Value::Number(delta_value)) => {
+                *acc_value = match (acc_value.as_u64(), delta_value.as_u64()) {
+                    (Some(acc_value), Some(delta_value)) => (acc_value + delta_value).into(),
+                    _ => serde_json::Number::from_f64(acc_value.as_f64().unwrap_or_default() + delta_value.as_f64().unwrap_or_default())
+                        .ok_or_else(|| Error::OpenAI(format!("Invalid number for {key}")))?,
+                };
+            }
+            (Value::Object(acc_value), Value::Object(delta_value)) => accumulate_delta(acc_value, delta_value)?,
+            (Value::Array(acc_value), Value::Array(delta_value)) => {
+                if acc_value.iter().all(|value| value.is_string() || value.is_number()) {
+                    acc_value.extend(delta_value);
+                    continue;
+                }
+
+                for delta_entry in delta_value {
+                    let Value::Object(delta_entry) = delta_entry else {
+                        return Err(Error::OpenAI(format!("Expected array delta entry to be an object but got: {delta_entry}")));
+                    };
+                    let index = delta_entry.get("index")
+                        .and_then(Value::as_u64)
+                        .ok_or_else(|| Error::OpenAI("Expected array delta entry to have a numeric `index` property".to_string()))?;
+
+                    match acc_value.get_mut(index as usize) {
+                        Some(Value::Object(acc_entry)) => accumulate_delta(acc_entry, delta_entry)?,
+                        _ => acc_value.push(Value::Object(delta_entry)),
+                    }
+                }
+            }
+            (acc_value, delta_value) => {
+                return Err(Error::OpenAI(format!("Unhandled record type: {key}, deltaValue: {delta_value}, accValue: {acc_value}")));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+    use crate::resources::beta::threads::{Annotation, RunStatus};
+    use crate::resources::beta::threads::messages::message;
+
+    fn event(event: &str, data: Value) -> Result<AssistantStreamEvent, Error> {
+        Ok(serde_json::from_value(json!({"event": event, "data": data})).unwrap())
+    }
+
+    fn run(status: &str) -> Value {
+        json!({
+            "id": "run_123",
+            "object": "thread.run",
+            "created_at": 1710330640,
+            "assistant_id": "asst_123",
+            "thread_id": "thread_123",
+            "status": status,
+            "instructions": "",
+            "model": "gpt-4o",
+            "parallel_tool_calls": true,
+            "tools": [],
+        })
+    }
+
+    fn message(status: &str, content: Value) -> Value {
+        json!({
+            "id": "msg_123",
+            "object": "thread.message",
+            "created_at": 1710330641,
+            "thread_id": "thread_123",
+            "run_id": "run_123",
+            "status": status,
+            "role": "assistant",
+            "content": content,
+        })
+    }
+
+    fn run_step(status: &str, step_details: Value) -> Value {
+        json!({
+            "id": "step_123",
+            "object": "thread.run.step",
+            "created_at": 1710330641,
+            "run_id": "run_123",
+            "assistant_id": "asst_123",
+            "thread_id": "thread_123",
+            "type": step_details["type"],
+            "status": status,
+            "step_details": step_details,
+        })
+    }
+
+    fn names(events: &[AssistantStream]) -> Vec<&'static str> {
+        events.iter().map(|event| match event {
+            AssistantStream::MessageCreated(_) => "message_created",
+            AssistantStream::MessageDelta { .. } => "message_delta",
+            AssistantStream::MessageDone(_) => "message_done",
+            AssistantStream::RunStepCreated(_) => "run_step_created",
+            AssistantStream::RunStepDelta { .. } => "run_step_delta",
+            AssistantStream::RunStepDone { .. } => "run_step_done",
+            AssistantStream::ToolCallCreated(_) => "tool_call_created",
+            AssistantStream::ToolCallDelta { .. } => "tool_call_delta",
+            AssistantStream::ToolCallDone(_) => "tool_call_done",
+            AssistantStream::TextCreated(_) => "text_created",
+            AssistantStream::TextDelta { .. } => "text_delta",
+            AssistantStream::TextDone { .. } => "text_done",
+            AssistantStream::ImageFileDone { .. } => "image_file_done",
+            AssistantStream::Event(_) => "event",
+            AssistantStream::Connect => "connect",
+            AssistantStream::Run(_) => "run",
+            AssistantStream::End => "end",
+        }).collect()
+    }
+
+    /// Collects the events of the runner, without the raw ones.
+    async fn collect(runner: &mut AssistantStreamRunner) -> Vec<AssistantStream> {
+        runner
+            .map(Result::unwrap)
+            .filter(|event| futures::future::ready(!matches!(event, AssistantStream::Event(_))))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn accumulates_message_snapshots() {
+        let text_delta = |index: u32, value: &str| json!({
+            "id": "msg_123",
+            "object": "thread.message.delta",
+            "delta": {"content": [{"index": index, "type": "text", "text": {"value": value}}]},
+        });
+        let annotation_delta = json!({
+            "id": "msg_123",
+            "object": "thread.message.delta",
+            "delta": {"content": [{"index": 0, "type": "text", "text": {"annotations": [
+                {"index": 0, "type": "file_path", "text": "sandbox:/a.csv", "start_index": 6, "end_index": 20, "file_path": {"file_id": "file-2"}},
+            ]}}]},
+        });
+        let image_delta = json!({
+            "id": "msg_123",
+            "object": "thread.message.delta",
+            "delta": {"content": [{"index": 1, "type": "image_file", "image_file": {"file_id": "file-3"}}]},
+        });
+        let completed = message("completed", json!([
+            {"type": "text", "text": {"value": "Hello world", "annotations": []}},
+            {"type": "image_file", "image_file": {"file_id": "file-3"}},
+        ]));
+        let mut runner = AssistantStreamRunner::new(futures::stream::iter(vec![
+            event("thread.run.created", run("queued")),
+            event("thread.message.created", message("in_progress", json!([]))),
+            event("thread.message.in_progress", message("in_progress", json!([]))),
+            event("thread.message.delta", text_delta(0, "Hello")),
+            event("thread.message.delta", text_delta(0, " world")),
+            event("thread.message.delta", annotation_delta),
+            event("thread.message.delta", image_delta),
+            event("thread.message.completed", completed),
+            event("thread.run.completed", run("completed")),
+        ]));
+
+        let events = collect(&mut runner).await;
+
+        assert_eq!(names(&events), [
+            "connect", "message_created",
+            "text_created", "message_delta", "text_delta",
+            "message_delta", "text_delta",
+            "message_delta", "text_delta",
+            "message_delta", "text_done",
+            "image_file_done", "message_done", "run", "end",
+        ]);
+        match &events[6] {
+            AssistantStream::TextDelta { delta, snapshot } => {
+                assert_eq!(delta.value.as_deref(), Some(" world"));
+                assert_eq!(snapshot.value, "Hello world");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        match &events[10] {
+            AssistantStream::TextDone { content, .. } => {
+                assert_eq!(content.value, "Hello world");
+                assert!(matches!(&content.annotations[..], [Annotation::FilePathAnnotation(annotation)] if annotation.file_path.file_id == "file-2"));
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        assert!(matches!(&events[11], AssistantStream::ImageFileDone { content, .. } if content.file_id == "file-3"));
+
+        let messages = runner.final_messages().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert!(matches!(messages[0].status, Some(message::Status::Completed)));
+        assert!(runner.current_message_snapshot().is_none());
+        assert_eq!(runner.final_run().await.unwrap().status, RunStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn accumulates_tool_calls_of_run_steps() {
+        let tool_call_delta = |tool_call: Value| json!({
+            "id": "step_123",
+            "object": "thread.run.step.delta",
+            "delta": {"step_details": {"type": "tool_calls", "tool_calls": [tool_call]}},
+        });
+        let empty_tool_calls = json!({"type": "tool_calls", "tool_calls": []});
+        let mut runner = AssistantStreamRunner::new(futures::stream::iter(vec![
+            event("thread.run.created", run("queued")),
+            event("thread.run.step.created", run_step("in_progress", empty_tool_calls.clone())),
+            event("thread.run.step.delta", tool_call_delta(json!(
+                {"index": 0, "id": "call_1", "type": "code_interpreter", "code_interpreter": {"input": "", "outputs": []}}
+            ))),
+            event("thread.run.step.delta", tool_call_delta(json!(
+                {"index": 0, "type": "code_interpreter", "code_interpreter": {"input": "print(1)", "outputs": [{"index": 0, "type": "logs", "logs": "1"}]}}
+            ))),
+            event("thread.run.step.delta", tool_call_delta(json!(
+                {"index": 1, "id": "call_2", "type": "function", "function": {"name": "get_weather", "arguments": ""}}
+            ))),
+            event("thread.run.step.delta", tool_call_delta(json!(
+                {"index": 1, "type": "function", "function": {"arguments": "{\"location\":"}}
+            ))),
+            event("thread.run.step.delta", tool_call_delta(json!(
+                {"index": 1, "type": "function", "function": {"arguments": "\"Paris\"}"}}
+            ))),
+            event("thread.run.requires_action", run("requires_action")),
+        ]));
+
+        let events = collect(&mut runner).await;
+
+        assert_eq!(names(&events), [
+            "connect", "run_step_created",
+            "tool_call_created", "run_step_delta",
+            "tool_call_delta", "run_step_delta",
+            "tool_call_done", "tool_call_created", "run_step_delta",
+            "tool_call_delta", "run_step_delta",
+            "tool_call_delta", "run_step_delta",
+            "tool_call_done", "run", "end",
+        ]);
+        match &events[6] {
+            AssistantStream::ToolCallDone(ToolCall::CodeInterpreter(tool_call)) => {
+                assert_eq!(tool_call.code_interpreter.input, "print(1)");
+                assert_eq!(tool_call.code_interpreter.outputs.len(), 1);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+        match &events[13] {
+            AssistantStream::ToolCallDone(ToolCall::Function(tool_call)) => {
+                assert_eq!(tool_call.id, "call_2");
+                assert_eq!(tool_call.function.arguments, "{\"location\":\"Paris\"}");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        let run_steps = runner.final_run_steps().await.unwrap();
+        match &run_steps[0].step_details {
+            run_step::StepDetails::ToolCallsStepDetails(details) => assert_eq!(details.tool_calls.len(), 2),
+            details => panic!("unexpected details {details:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_stream_ends_without_a_final_run() {
+        let mut runner = AssistantStreamRunner::new(futures::stream::iter(vec![
+            event("thread.run.created", run("queued")),
+        ]));
+
+        assert!(runner.final_run().await.unwrap_err().to_string().contains("Final run has not been received"));
+    }
+
+    #[test]
+    fn accumulate_delta_appends_strings_and_merges_indexed_entries() {
+        let mut acc = json!({"value": "He", "annotations": [{"text": "a"}], "index": 0}).as_object().unwrap().clone();
+        let delta = json!({"value": "llo", "annotations": [{"index": 0, "text": "b"}, {"index": 1, "text": "c"}], "index": 0, "detail": null});
+
+        accumulate_delta(&mut acc, delta.as_object().unwrap().clone()).unwrap();
+
+        assert_eq!(Value::Object(acc), json!({
+            "value": "Hello",
+            "annotations": [{"index": 0, "text": "ab"}, {"index": 1, "text": "c"}],
+            "index": 0,
+        }));
+    }
+}
@@ -1 +1,3 @@
-pub mod assistant_stream;
\ No newline at end of file
+pub mod assistant_event_handler;
+pub mod assistant_stream;
+pub mod run_tools;
\ No newline at end of file
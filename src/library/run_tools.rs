@@ -0,0 +1,174 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use futures::future::{self, LocalBoxFuture};
+use futures::FutureExt;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use crate::error::Error;
+use crate::resources::beta::threads::RequiredActionFunctionToolCall;
+use crate::resources::beta::threads::runs::runs::run_submit_tool_outputs_params::ToolOutput;
+
+type Function = Arc<dyn Fn(String) -> LocalBoxFuture<'static, Result<String, Error>> + Send + Sync>;
+type ErrorOutput = Arc<dyn Fn(&str, &Error) -> String + Send + Sync>;
+
+/// The functions an assistant can call, by name, used by the `*_with_tools` helpers of `Runs`
+/// to answer the runs that require action.
+///
+/// The function calls of a run are made in parallel. A call that fails, times out or targets
+/// an unknown function is submitted as an error output, see [`RunTools::on_error`].
+#[derive(Clone)]
+pub struct RunTools {
+    functions: HashMap<String, (Function, Option<Duration>)>,
+    timeout: Option<Duration>,
+    error_output: ErrorOutput,
+}
+
+impl Default for RunTools {
+    fn default() -> Self {
+        RunTools {
+            functions: HashMap::new(),
+            timeout: None,
+            error_output: Arc::new(|_, error| json!({"error": error.to_string()}).to_string()),
+        }
+    }
+}
+
+impl RunTools {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the function called with the parsed arguments of the `name` tool calls.
+    /// Its output is submitted as is.
+    pub fn function<Args, F, Fut>(&mut self, name: &str, function: F) -> &mut Self
+    where
+        Args: DeserializeOwned + 'static,
+        F: Fn(Args) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=Result<String, Error>> + 'static,
+    {
+        self.register(name, None, function)
+    }
+
+    /// Like [`RunTools::function`], failing the calls that take longer than `timeout`.
+    pub fn function_with_timeout<Args, F, Fut>(&mut self, name: &str, timeout: Duration, function: F) -> &mut Self
+    where
+        Args: DeserializeOwned + 'static,
+        F: Fn(Args) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=Result<String, Error>> + 'static,
+    {
+        self.register(name, Some(timeout), function)
+    }
+
+    /// The timeout of the functions registered without one.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Builds the output submitted for a failed call from the name of the function and the error.
+    ///
+    /// Defaults to `{"error": "<message>"}`.
+    pub fn on_error(&mut self, error_output: impl Fn(&str, &Error) -> String + Send + Sync + 'static) -> &mut Self {
+        self.error_output = Arc::new(error_output);
+        self
+    }
+
+    fn register<Args, F, Fut>(&mut self, name: &str, timeout: Option<Duration>, function: F) -> &mut Self
+    where
+        Args: DeserializeOwned + 'static,
+        F: Fn(Args) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output=Result<String, Error>> + 'static,
+    {
+        let function = Arc::new(function);
+        let function_name = name.to_string();
+        let call: Function = Arc::new(move |arguments: String| {
+            match serde_json::from_str::<Args>(&arguments) {
+                Ok(arguments) => function(arguments).boxed_local(),
+                Err(error) => {
+                    let error = Error::OpenAI(format!("Invalid arguments for `{function_name}`: {error}"));
+                    future::ready(Err(error)).boxed_local()
+                }
+            }
+        });
+        self.functions.insert(name.to_string(), (call, timeout));
+        self
+    }
+
+    /// Calls the function of the tool call and returns the output to submit.
+    pub async fn call(&self, tool_call: &RequiredActionFunctionToolCall) -> ToolOutput {
+        let name = &tool_call.function.name;
+        let output = match self.functions.get(name) {
+            Some((function, timeout)) => {
+                let call = function(tool_call.function.arguments.clone());
+                match timeout.or(self.timeout) {
+                    Some(timeout) => tokio::time::timeout(timeout, call).await
+                        .unwrap_or_else(|_| Err(Error::OpenAI(format!("`{name}` timed out after {timeout:?}")))),
+                    None => call.await,
+                }
+            }
+            None => Err(Error::OpenAI(format!("Unknown function `{name}`"))),
+        };
+
+        ToolOutput {
+            output: Some(output.unwrap_or_else(|error| (self.error_output)(name, &error))),
+            tool_call_id: Some(tool_call.id.clone()),
+        }
+    }
+
+    /// Calls the functions of every tool call in parallel, returning their outputs in order.
+    pub async fn call_all(&self, tool_calls: &[RequiredActionFunctionToolCall]) -> Vec<ToolOutput> {
+        future::join_all(tool_calls.iter().map(|tool_call| self.call(tool_call))).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use super::*;
+    use crate::resources::beta::threads::runs::runs::required_action_function_tool_call;
+
+    fn tool_call(id: &str, name: &str, arguments: &str) -> RequiredActionFunctionToolCall {
+        RequiredActionFunctionToolCall {
+            id: id.to_string(),
+            function: required_action_function_tool_call::Function {
+                arguments: arguments.to_string(),
+                name: name.to_string(),
+            },
+            ..Default::default()
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct Location {
+        location: String,
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn call_all_maps_failures_to_error_outputs() {
+        let mut tools = RunTools::new();
+        tools
+            .function("get_weather", |args: Location| async move { Ok(format!("22C in {}", args.location)) })
+            .function_with_timeout("slow", Duration::from_secs(1), |_: serde_json::Value| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok("late".to_string())
+            })
+            .function("broken", |_: serde_json::Value| async { Err(Error::OpenAI("boom".to_string())) });
+
+        let outputs = tools.call_all(&[
+            tool_call("call_1", "get_weather", r#"{"location":"Paris"}"#),
+            tool_call("call_2", "get_weather", r#"{"city":"Paris"}"#),
+            tool_call("call_3", "slow", "{}"),
+            tool_call("call_4", "broken", "{}"),
+            tool_call("call_5", "missing", "{}"),
+        ]).await;
+
+        let outputs: Vec<_> = outputs.iter().map(|output| output.output.as_deref().unwrap()).collect();
+        assert_eq!(outputs[0], "22C in Paris");
+        assert!(outputs[1].starts_with(r#"{"error":"Invalid arguments for `get_weather`: missing field `location`"#));
+        assert_eq!(outputs[2], r#"{"error":"`slow` timed out after 1s"}"#);
+        assert_eq!(outputs[3], r#"{"error":"boom"}"#);
+        assert_eq!(outputs[4], r#"{"error":"Unknown function `missing`"}"#);
+    }
+}
@@ -127,37 +127,43 @@
 
 /////////////////////////////////////////////////////////////////
 
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::error::Error;
-use std::rc::Rc;
-use std::sync::{Arc, Mutex};
-use futures::Stream;
-use reqwest::Response;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures::future::LocalBoxFuture;
+use futures::{FutureExt, Stream};
 use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use crate::core::{APIClient, FinalRequestOptions, RequestOptions, PageInfo};
+use serde_json::Value;
+use crate::error::Error;
+use crate::core::{FinalRequestOptions, PageInfo};
 use crate::resource::APIResource;
-// use crate::resources::beta::assistants::Assistant;
-// use crate::resources::beta::threads::{Run, RunListParams};
 
+#[allow(async_fn_in_trait)]
 pub trait Page<Req: Default + Clone + Serialize, Item: for<'de> Deserialize<'de>>: Sized {
     fn new(client: APIResource, /*response: reqwest::Response,*/ body: CursorPageResponse<Item>, options: FinalRequestOptions<Req>) -> Self;
     fn next_page_info(&self) -> Option<PageInfo>;
     fn get_paginated_items(&self) -> Vec<Item>;
     fn has_next_page(&self) -> bool;
-    async fn get_next_page(&self) -> Result<Self, Box<dyn Error>>;
+    async fn get_next_page(&self) -> Result<Self, Error>;
     async fn iter_pages(&self) -> &Self;
-    // async fn iter_pages(&mut self) -> impl Iterator<Item = Result<Self, Box<dyn Error>>> + '_
+    // async fn iter_pages(&mut self) -> impl Iterator<Item = Result<Self, Error>> + '_
+}
+
+/// An item of a cursor page. Its `id` is the cursor of the page that follows it.
+pub trait CursorItem {
+    fn id(&self) -> &str;
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct CursorPageResponse<Item> {
     object: CursorPageResponseObject,
     data: Vec<Item>,
-    first_id: String,
-    last_id: String,
-    has_more: bool
+    #[serde(default)]
+    first_id: Option<String>,
+    #[serde(default)]
+    last_id: Option<String>,
+    #[serde(default)]
+    has_more: Option<bool>,
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -169,11 +175,20 @@ pub enum CursorPageResponseObject {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct CursorPageParams {
-    pub after: String,
-    pub limit: u32,
+    /// A cursor for use in pagination. `after` is an object ID that defines your place
+    /// in the list. For instance, if you make a list request and receive 100 objects,
+    /// ending with obj_foo, your subsequent call can include after=obj_foo in order to
+    /// fetch the next page of the list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+
+    /// A limit on the number of objects to be returned. Limit can range between 1 and
+    /// 100, and the default is 20.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CursorPage<Req: Default + Clone + Serialize, Item: for<'de> Deserialize<'de>> { // <Item extends { id: string }>
     pub data: Vec<Item>,
     pub client: APIResource,
@@ -185,75 +200,63 @@ pub struct CursorPage<Req: Default + Clone + Serialize, Item: for<'de> Deseriali
 // extends AbstractPage<Item>
 // implements CursorPageResponse<Item>
 
-impl<Req: Default + Clone + Serialize, Item: for<'de> Deserialize<'de> + Clone + 'static> Page<Req, Item> for CursorPage<Req, Item> {
+impl<Req, Item> Page<Req, Item> for CursorPage<Req, Item>
+where
+    Req: Default + Clone + Serialize + for<'de> Deserialize<'de>,
+    Item: for<'de> Deserialize<'de> + Clone + CursorItem + 'static,
+{
     fn new(
         client: APIResource,
         // response: reqwest::Response,
         body: CursorPageResponse<Item>,
         options: FinalRequestOptions<Req>,
     ) -> Self {
-        // super(client, response, body, options);
-
-        // this.data = body.data || [];
         CursorPage {
-            client: client,
+            client,
             // response: response,
             data: body.data.clone(),
-            body: body,
-            options: options,
+            body,
+            options,
         }
     }
 
+    /// The `after` cursor of the next page, the id of the last item of this one.
     fn next_page_info(&self) -> Option<PageInfo> {
-        let data = self.get_paginated_items();
-        if data.len() == 0 {
+        let id = self.data.last()?.id();
+        if id.is_empty() {
             return None;
         }
 
-        // let id = data[data.len() - 1]?.id;
-        // if !id {
-        //     return None;
-        // }
-
-        // let mut hash_map: HashMap<String, Value> = HashMap::new();
-        // hash_map.insert("after".to_string(), Value::String(id));
-        // Some(PageInfo::Params(Some(hash_map)))
-        None
+        Some(PageInfo::Params(Some([("after".to_string(), Value::String(id.to_string()))].into())))
     }
 
     fn get_paginated_items(&self) -> Vec<Item> {
-        // self.data.clone()
-        vec![]
+        self.data.clone()
     }
 
     fn has_next_page(&self) -> bool {
-        let items = self.get_paginated_items();
-        if items.len() == 0 {
+        if self.body.has_more == Some(false) || self.data.is_empty() {
             return false;
         }
-        return self.next_page_info().is_some();
+        self.next_page_info().is_some()
     }
 
-    async fn get_next_page(&self) -> Result<Self, Box<dyn Error>> {
-        let next_info = self.next_page_info();
-        if next_info.is_none() {
-            // throw new OpenAIError(
-            //     'No next page expected; please check `.hasNextPage()` before calling `.getNextPage()`.',
-            // );
-            // return self
-        }
-        let mut next_info = next_info.unwrap();
+    /// Requests the page that follows this one, with the same params and options.
+    async fn get_next_page(&self) -> Result<Self, Error> {
+        let Some(next_info) = self.next_page_info().filter(|_| self.has_next_page()) else {
+            return Err(Error::OpenAI(
+                "No next page expected; please check `has_next_page()` before calling `get_next_page()`.".to_string()
+            ));
+        };
+
         let mut next_options = self.options.clone();
         match next_info {
             PageInfo::Params(params) => {
-                // let mut query = next_options.query.unwrap_or_default();
-                // if let Some(params) = params {
-                //     for (key, value) in params {
-                //         // query.insert(key, value);
-                //     }
-                // }
-                // next_options.query = Some(query);
-                return Err("Not implemented".into());
+                let mut query = serde_json::to_value(next_options.query.take().unwrap_or_default())?;
+                if let (Value::Object(query), Some(params)) = (&mut query, params) {
+                    query.extend(params);
+                }
+                next_options.query = Some(serde_json::from_value(query)?);
             }
             PageInfo::Url(url) => {
                 next_options.query = None;
@@ -269,12 +272,150 @@ impl<Req: Default + Clone + Serialize, Item: for<'de> Deserialize<'de> + Clone +
             CursorPage::new(client, body, options)
         };
 
-        let result = self.client.clone().lock().unwrap().request_api_list(page_constructor, next_options).await.unwrap();
-        
-        Ok(result)
+        let client = self.client.lock().unwrap().clone();
+        client.request_api_list(page_constructor, next_options).await
     }
 
     async fn iter_pages(&self) -> &Self {
         self
     }
 }
+
+impl<Req, Item> CursorPage<Req, Item>
+where
+    Req: Default + Clone + Serialize + for<'de> Deserialize<'de> + 'static,
+    Item: for<'de> Deserialize<'de> + Clone + CursorItem + 'static,
+{
+    /// Yields the items of this page, then the items of every following page, requesting
+    /// each page once the items before it have been consumed.
+    ///
+    /// ```ignore
+    /// let mut assistants = openai.beta.assistants.list(None, None).await?.into_stream();
+    /// while let Some(assistant) = assistants.next().await {
+    ///     openai.beta.assistants.del(&assistant?.id, None).await?;
+    /// }
+    /// ```
+    pub fn into_stream(self) -> CursorPageStream<Req, Item> {
+        CursorPageStream::new(self)
+    }
+
+    /// Like [`CursorPage::into_stream`], keeping this page.
+    pub fn iter_all(&self) -> CursorPageStream<Req, Item> {
+        self.clone().into_stream()
+    }
+}
+
+/// The items of a cursor page and of the pages after it, see [`CursorPage::into_stream`].
+///
+/// The stream ends after the last page, after `max_items` items, or after yielding the
+/// error of a failed page request, once the items received before it.
+pub struct CursorPageStream<Req: Default + Clone + Serialize, Item: for<'de> Deserialize<'de>> {
+    items: VecDeque<Item>,
+    next_page: Option<LocalBoxFuture<'static, Result<CursorPage<Req, Item>, Error>>>,
+    error: Option<Error>,
+    remaining: Option<usize>,
+    prefetch: bool,
+    /// How many of the buffered items belong to a page prefetched before the items of the
+    /// page being consumed ran out.
+    prefetched: usize,
+}
+
+// The stream is never pin projected.
+impl<Req: Default + Clone + Serialize, Item: for<'de> Deserialize<'de>> Unpin for CursorPageStream<Req, Item> {}
+
+impl<Req, Item> CursorPageStream<Req, Item>
+where
+    Req: Default + Clone + Serialize + for<'de> Deserialize<'de> + 'static,
+    Item: for<'de> Deserialize<'de> + Clone + CursorItem + 'static,
+{
+    fn new(page: CursorPage<Req, Item>) -> Self {
+        let mut stream = CursorPageStream {
+            items: VecDeque::new(),
+            next_page: None,
+            error: None,
+            remaining: None,
+            prefetch: false,
+            prefetched: 0,
+        };
+        stream.push_page(page);
+        stream
+    }
+
+    /// Ends the stream after `max_items` items, without requesting the pages past them.
+    pub fn max_items(mut self, max_items: usize) -> Self {
+        self.remaining = Some(max_items);
+        self
+    }
+
+    /// Requests the next page as soon as the consumption of the current one starts, instead
+    /// of once its items have been consumed.
+    ///
+    /// The stream stays at most one page ahead: the page after the prefetched one is only
+    /// requested once the items of the prefetched page are being consumed.
+    pub fn prefetch(mut self, prefetch: bool) -> Self {
+        self.prefetch = prefetch;
+        self
+    }
+
+    fn push_page(&mut self, page: CursorPage<Req, Item>) {
+        if !self.items.is_empty() {
+            self.prefetched = page.data.len();
+        }
+        self.items.extend(page.data.iter().cloned());
+        self.next_page = page.has_next_page()
+            .then(|| async move { page.get_next_page().await }.boxed_local());
+    }
+
+    fn needs_next_page(&self) -> bool {
+        match self.remaining {
+            Some(remaining) if self.items.len() >= remaining => false,
+            _ => self.items.is_empty() || (self.prefetch && self.prefetched == 0),
+        }
+    }
+}
+
+impl<Req, Item> Stream for CursorPageStream<Req, Item>
+where
+    Req: Default + Clone + Serialize + for<'de> Deserialize<'de> + 'static,
+    Item: for<'de> Deserialize<'de> + Clone + CursorItem + 'static,
+{
+    type Item = Result<Item, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.remaining == Some(0) {
+            return Poll::Ready(None);
+        }
+
+        // Only the items of the prefetched page are left: it is the page being consumed now.
+        if this.items.len() == this.prefetched {
+            this.prefetched = 0;
+        }
+
+        while this.needs_next_page() {
+            let Some(next_page) = this.next_page.as_mut() else { break };
+            match next_page.poll_unpin(cx) {
+                Poll::Ready(Ok(page)) => this.push_page(page),
+                Poll::Ready(Err(error)) => {
+                    this.next_page = None;
+                    this.error = Some(error);
+                }
+                Poll::Pending => break,
+            }
+        }
+
+        match this.items.pop_front() {
+            Some(item) => {
+                if let Some(remaining) = this.remaining.as_mut() {
+                    *remaining -= 1;
+                }
+                Poll::Ready(Some(Ok(item)))
+            }
+            None if this.next_page.is_some() => Poll::Pending,
+            None => {
+                this.remaining = Some(0);
+                Poll::Ready(this.error.take().map(Err))
+            }
+        }
+    }
+}
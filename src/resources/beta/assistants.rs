@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::Error;
 use std::rc::Rc;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -13,7 +13,7 @@ use crate::resources::beta::threads::messages as messages_api;
 use crate::resources::beta::threads as threads_api;
 use crate::resources::beta::threads::runs::runs as runs_api;
 use crate::resources::beta::threads::runs::steps as steps_api;
-use crate::pagination::{Page, CursorPage, CursorPageParams, CursorPageResponse};
+use crate::pagination::{Page, CursorItem, CursorPage, CursorPageParams, CursorPageResponse};
 
 #[derive(Debug, Clone)]
 pub struct Assistants {
@@ -121,10 +121,17 @@ impl Assistants {
     pub async fn list(
         &self,
         query: AssistantListParams,
-        _options: Option<RequestOptions<AssistantListParams>>,
-    ) -> Result<CursorPage<AssistantListParams, Assistant>, Box<dyn Error>> {
+        options: Option<RequestOptions<AssistantListParams>>,
+    ) -> Result<CursorPage<AssistantListParams, Assistant>, Error> {
         let mut headers: Headers = HashMap::new();
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
+        if let Some(opts) = &options {
+            if let Some(hdrs) = &opts.headers {
+                for (key, value) in hdrs {
+                    headers.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
 
         let page_constructor = |
             client: APIResource,
@@ -138,9 +145,9 @@ impl Assistants {
             "/assistants",
             page_constructor,
             Some(RequestOptions {
-                // query: Some(query),
+                query: Some(query),
                 headers: Some(headers),
-                ..Default::default()
+                ..options.unwrap_or_default()
             }),
         ).await
     }
@@ -252,6 +259,12 @@ pub struct Assistant {
     pub top_p: Option<f32>,
 }
 
+impl CursorItem for Assistant {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
 pub mod assistant {
     use serde::{Deserialize, Serialize};
 
@@ -317,299 +330,200 @@ pub mod assistant_deleted {
     }
 }
 
-// /// Represents an event emitted when streaming a Run.
-// ///
-// /// Each event in a server-sent events stream has an `event` and `data` property:
-// ///
-// /// ```
-// /// event: thread.created
-// /// data: {"id": "thread_123", "object": "thread", ...}
-// /// ```
-// ///
-// /// We emit events whenever a new object is created, transitions to a new state, or
-// /// is being streamed in parts (deltas). For example, we emit `thread.run.created`
-// /// when a new run is created, `thread.run.completed` when a run completes, and so
-// /// on. When an Assistant chooses to create a message during a run, we emit a
-// /// `thread.message.created event`, a `thread.message.in_progress` event, many
-// /// `thread.message.delta` events, and finally a `thread.message.completed` event.
-// ///
-// /// We may add additional events over time, so we recommend handling unknown events
-// /// gracefully in your code. See the
-// /// [Assistants API quickstart](https://platform.openai.com/docs/assistants/overview)
-// /// to learn how to integrate the Assistants API with streaming.
-// export type AssistantStreamEvent =
-//   | AssistantStreamEvent.ThreadCreated
-//   | AssistantStreamEvent.ThreadRunCreated
-//   | AssistantStreamEvent.ThreadRunQueued
-//   | AssistantStreamEvent.ThreadRunInProgress
-//   | AssistantStreamEvent.ThreadRunRequiresAction
-//   | AssistantStreamEvent.ThreadRunCompleted
-//   | AssistantStreamEvent.ThreadRunIncomplete
-//   | AssistantStreamEvent.ThreadRunFailed
-//   | AssistantStreamEvent.ThreadRunCancelling
-//   | AssistantStreamEvent.ThreadRunCancelled
-//   | AssistantStreamEvent.ThreadRunExpired
-//   | AssistantStreamEvent.ThreadRunStepCreated
-//   | AssistantStreamEvent.ThreadRunStepInProgress
-//   | AssistantStreamEvent.ThreadRunStepDelta
-//   | AssistantStreamEvent.ThreadRunStepCompleted
-//   | AssistantStreamEvent.ThreadRunStepFailed
-//   | AssistantStreamEvent.ThreadRunStepCancelled
-//   | AssistantStreamEvent.ThreadRunStepExpired
-//   | AssistantStreamEvent.ThreadMessageCreated
-//   | AssistantStreamEvent.ThreadMessageInProgress
-//   | AssistantStreamEvent.ThreadMessageDelta
-//   | AssistantStreamEvent.ThreadMessageCompleted
-//   | AssistantStreamEvent.ThreadMessageIncomplete
-//   | AssistantStreamEvent.ErrorEvent;
-// 
-// pub mod AssistantStreamEvent {
-//   /// Occurs when a new
-//   /// [thread](https://platform.openai.com/docs/api-reference/threads/object) is
-//   /// created.
-//   pub struct ThreadCreated {
-//     /// Represents a thread that contains
-//     /// [messages](https://platform.openai.com/docs/api-reference/messages).
-//     data: ThreadsAPI.Thread;
-// 
-//     event: 'thread.created';
-//   }
-// 
-//   /// Occurs when a new
-//   /// [run](https://platform.openai.com/docs/api-reference/runs/object) is created.
-//   pub struct ThreadRunCreated {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.created';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// moves to a `queued` status.
-//   pub struct ThreadRunQueued {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.queued';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// moves to an `in_progress` status.
-//   pub struct ThreadRunInProgress {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.in_progress';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// moves to a `requires_action` status.
-//   pub struct ThreadRunRequiresAction {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.requires_action';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// is completed.
-//   pub struct ThreadRunCompleted {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.completed';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// ends with status `incomplete`.
-//   pub struct ThreadRunIncomplete {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.incomplete';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// fails.
-//   pub struct ThreadRunFailed {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.failed';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// moves to a `cancelling` status.
-//   pub struct ThreadRunCancelling {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.cancelling';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// is cancelled.
-//   pub struct ThreadRunCancelled {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.cancelled';
-//   }
-// 
-//   /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
-//   /// expires.
-//   pub struct ThreadRunExpired {
-//     /// Represents an execution run on a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: RunsAPI.Run;
-// 
-//     event: 'thread.run.expired';
-//   }
-// 
-//   /// Occurs when a
-//   /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) is
-//   /// created.
-//   pub struct ThreadRunStepCreated {
-//     /// Represents a step in execution of a run.
-//     data: StepsAPI.RunStep;
-// 
-//     event: 'thread.run.step.created';
-//   }
-// 
-//   /// Occurs when a
-//   /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object)
-//   /// moves to an `in_progress` state.
-//   pub struct ThreadRunStepInProgress {
-//     /// Represents a step in execution of a run.
-//     data: StepsAPI.RunStep;
-// 
-//     event: 'thread.run.step.in_progress';
-//   }
-// 
-//   /// Occurs when parts of a
-//   /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) are
-//   /// being streamed.
-//   pub struct ThreadRunStepDelta {
-//     /// Represents a run step delta i.e. any changed fields on a run step during
-//     /// streaming.
-//     data: StepsAPI.RunStepDeltaEvent;
-// 
-//     event: 'thread.run.step.delta';
-//   }
-// 
-//   /// Occurs when a
-//   /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) is
-//   /// completed.
-//   pub struct ThreadRunStepCompleted {
-//     /// Represents a step in execution of a run.
-//     data: StepsAPI.RunStep;
-// 
-//     event: 'thread.run.step.completed';
-//   }
-// 
-//   /// Occurs when a
-//   /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object)
-//   /// fails.
-//   pub struct ThreadRunStepFailed {
-//     /// Represents a step in execution of a run.
-//     data: StepsAPI.RunStep;
-// 
-//     event: 'thread.run.step.failed';
-//   }
-// 
-//   /// Occurs when a
-//   /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) is
-//   /// cancelled.
-//   pub struct ThreadRunStepCancelled {
-//     /// Represents a step in execution of a run.
-//     data: StepsAPI.RunStep;
-// 
-//     event: 'thread.run.step.cancelled';
-//   }
-// 
-//   /// Occurs when a
-//   /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object)
-//   /// expires.
-//   pub struct ThreadRunStepExpired {
-//     /// Represents a step in execution of a run.
-//     data: StepsAPI.RunStep;
-// 
-//     event: 'thread.run.step.expired';
-//   }
-// 
-//   /// Occurs when a
-//   /// [message](https://platform.openai.com/docs/api-reference/messages/object) is
-//   /// created.
-//   pub struct ThreadMessageCreated {
-//     /// Represents a message within a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: messages_api::Message;
-// 
-//     event: 'thread.message.created';
-//   }
-// 
-//   /// Occurs when a
-//   /// [message](https://platform.openai.com/docs/api-reference/messages/object) moves
-//   /// to an `in_progress` state.
-//   pub struct ThreadMessageInProgress {
-//     /// Represents a message within a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: messages_api::Message;
-// 
-//     event: 'thread.message.in_progress';
-//   }
-// 
-//   /// Occurs when parts of a
-//   /// [Message](https://platform.openai.com/docs/api-reference/messages/object) are
-//   /// being streamed.
-//   pub struct ThreadMessageDelta {
-//     /// Represents a message delta i.e. any changed fields on a message during
-//     /// streaming.
-//     data: messages_api::MessageDeltaEvent;
-// 
-//     event: 'thread.message.delta';
-//   }
-// 
-//   /// Occurs when a
-//   /// [message](https://platform.openai.com/docs/api-reference/messages/object) is
-//   /// completed.
-//   pub struct ThreadMessageCompleted {
-//     /// Represents a message within a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: messages_api::Message;
-// 
-//     event: 'thread.message.completed';
-//   }
-// 
-//   /// Occurs when a
-//   /// [message](https://platform.openai.com/docs/api-reference/messages/object) ends
-//   /// before it is completed.
-//   pub struct ThreadMessageIncomplete {
-//     /// Represents a message within a
-//     /// [thread](https://platform.openai.com/docs/api-reference/threads).
-//     data: messages_api::Message;
-// 
-//     event: 'thread.message.incomplete';
-//   }
-// 
-//   /// Occurs when an
-//   /// [error](https://platform.openai.com/docs/guides/error-codes/api-errors) occurs.
-//   /// This can happen due to an internal server error or a timeout.
-//   pub struct ErrorEvent {
-//     data: Shared.ErrorObject;
-// 
-//     event: 'error';
-//   }
-// }
+/// Declares [`AssistantStreamEvent`] from the name of each server-sent event and the type
+/// of its payload, with the (de)serialization of the `{"event": ..., "data": ...}` pairs.
+macro_rules! assistant_stream_events {
+    ($($(#[$doc:meta])* $name:literal => $variant:ident($data:ty),)*) => {
+        /// Represents an event emitted when streaming a Run.
+        ///
+        /// Each event in a server-sent events stream has an `event` and `data` property:
+        ///
+        /// ```text
+        /// event: thread.created
+        /// data: {"id": "thread_123", "object": "thread", ...}
+        /// ```
+        ///
+        /// We emit events whenever a new object is created, transitions to a new state, or
+        /// is being streamed in parts (deltas). For example, we emit `thread.run.created`
+        /// when a new run is created, `thread.run.completed` when a run completes, and so
+        /// on. When an Assistant chooses to create a message during a run, we emit a
+        /// `thread.message.created event`, a `thread.message.in_progress` event, many
+        /// `thread.message.delta` events, and finally a `thread.message.completed` event.
+        ///
+        /// We may add additional events over time, so we recommend handling unknown events
+        /// gracefully in your code. See the
+        /// [Assistants API quickstart](https://platform.openai.com/docs/assistants/overview)
+        /// to learn how to integrate the Assistants API with streaming.
+        #[derive(Debug, Clone)]
+        pub enum AssistantStreamEvent {
+            $($(#[$doc])* $variant($data),)*
+            /// An event this version of the library doesn't know about, as it was received.
+            Unknown {
+                event: String,
+                data: Value,
+            },
+        }
+
+        impl AssistantStreamEvent {
+            /// The name of the server-sent event, e.g. `thread.run.created`.
+            pub fn event(&self) -> &str {
+                match self {
+                    $(AssistantStreamEvent::$variant(_) => $name,)*
+                    AssistantStreamEvent::Unknown { event, .. } => event,
+                }
+            }
+        }
+
+        impl Serialize for AssistantStreamEvent {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use serde::ser::SerializeStruct;
+
+                let mut event = serializer.serialize_struct("AssistantStreamEvent", 2)?;
+                event.serialize_field("event", self.event())?;
+                match self {
+                    $(AssistantStreamEvent::$variant(data) => event.serialize_field("data", data)?,)*
+                    AssistantStreamEvent::Unknown { data, .. } => event.serialize_field("data", data)?,
+                }
+                event.end()
+            }
+        }
+
+        impl<'de> Deserialize<'de> for AssistantStreamEvent {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                use serde::de::Error as _;
+
+                #[derive(Deserialize)]
+                struct RawEvent {
+                    event: String,
+                    #[serde(default)]
+                    data: Value,
+                }
+
+                let RawEvent { event, data } = RawEvent::deserialize(deserializer)?;
+                match event.as_str() {
+                    $($name => serde_json::from_value(data)
+                        .map(AssistantStreamEvent::$variant)
+                        .map_err(|error| D::Error::custom(format!("invalid `{}` event: {error}", $name))),)*
+                    _ => Ok(AssistantStreamEvent::Unknown { event, data }),
+                }
+            }
+        }
+    };
+}
+
+assistant_stream_events! {
+    /// Occurs when a new
+    /// [thread](https://platform.openai.com/docs/api-reference/threads/object) is
+    /// created.
+    "thread.created" => ThreadCreated(threads_api::Thread),
+
+    /// Occurs when a new
+    /// [run](https://platform.openai.com/docs/api-reference/runs/object) is created.
+    "thread.run.created" => ThreadRunCreated(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// moves to a `queued` status.
+    "thread.run.queued" => ThreadRunQueued(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// moves to an `in_progress` status.
+    "thread.run.in_progress" => ThreadRunInProgress(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// moves to a `requires_action` status.
+    "thread.run.requires_action" => ThreadRunRequiresAction(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// is completed.
+    "thread.run.completed" => ThreadRunCompleted(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// ends with status `incomplete`.
+    "thread.run.incomplete" => ThreadRunIncomplete(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// fails.
+    "thread.run.failed" => ThreadRunFailed(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// moves to a `cancelling` status.
+    "thread.run.cancelling" => ThreadRunCancelling(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// is cancelled.
+    "thread.run.cancelled" => ThreadRunCancelled(runs_api::Run),
+
+    /// Occurs when a [run](https://platform.openai.com/docs/api-reference/runs/object)
+    /// expires.
+    "thread.run.expired" => ThreadRunExpired(runs_api::Run),
+
+    /// Occurs when a
+    /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) is
+    /// created.
+    "thread.run.step.created" => ThreadRunStepCreated(steps_api::RunStep),
+
+    /// Occurs when a
+    /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object)
+    /// moves to an `in_progress` state.
+    "thread.run.step.in_progress" => ThreadRunStepInProgress(steps_api::RunStep),
+
+    /// Occurs when parts of a
+    /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) are
+    /// being streamed.
+    "thread.run.step.delta" => ThreadRunStepDelta(steps_api::RunStepDeltaEvent),
+
+    /// Occurs when a
+    /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) is
+    /// completed.
+    "thread.run.step.completed" => ThreadRunStepCompleted(steps_api::RunStep),
+
+    /// Occurs when a
+    /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object)
+    /// fails.
+    "thread.run.step.failed" => ThreadRunStepFailed(steps_api::RunStep),
+
+    /// Occurs when a
+    /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object) is
+    /// cancelled.
+    "thread.run.step.cancelled" => ThreadRunStepCancelled(steps_api::RunStep),
+
+    /// Occurs when a
+    /// [run step](https://platform.openai.com/docs/api-reference/runs/step-object)
+    /// expires.
+    "thread.run.step.expired" => ThreadRunStepExpired(steps_api::RunStep),
+
+    /// Occurs when a
+    /// [message](https://platform.openai.com/docs/api-reference/messages/object) is
+    /// created.
+    "thread.message.created" => ThreadMessageCreated(messages_api::Message),
+
+    /// Occurs when a
+    /// [message](https://platform.openai.com/docs/api-reference/messages/object) moves
+    /// to an `in_progress` state.
+    "thread.message.in_progress" => ThreadMessageInProgress(messages_api::Message),
+
+    /// Occurs when parts of a
+    /// [Message](https://platform.openai.com/docs/api-reference/messages/object) are
+    /// being streamed.
+    "thread.message.delta" => ThreadMessageDelta(messages_api::MessageDeltaEvent),
+
+    /// Occurs when a
+    /// [message](https://platform.openai.com/docs/api-reference/messages/object) is
+    /// completed.
+    "thread.message.completed" => ThreadMessageCompleted(messages_api::Message),
+
+    /// Occurs when a
+    /// [message](https://platform.openai.com/docs/api-reference/messages/object) ends
+    /// before it is completed.
+    "thread.message.incomplete" => ThreadMessageIncomplete(messages_api::Message),
+
+    /// Occurs when an
+    /// [error](https://platform.openai.com/docs/guides/error-codes/api-errors) occurs.
+    /// This can happen due to an internal server error or a timeout.
+    ///
+    /// `APIFuture::into_stream` fails the stream with the error instead of yielding it.
+    "error" => ErrorEvent(crate::ErrorObject),
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -1373,16 +1287,28 @@ pub mod assistant_update_params {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct AssistantListParams { // extends CursorPageParams
+    /// A cursor for use in pagination. `after` is an object ID that defines your place
+    /// in the list. For instance, if you make a list request and receive 100 objects,
+    /// ending with obj_foo, your subsequent call can include after=obj_foo in order to
+    /// fetch the next page of the list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+
     /// A cursor for use in pagination. `before` is an object ID that defines your place
     /// in the list. For instance, if you make a list request and receive 100 objects,
     /// ending with obj_foo, your subsequent call can include before=obj_foo in order to
     /// fetch the previous page of the list.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub before: Option<String>,
 
     /// Sort order by the `created_at` timestamp of the objects. `asc` for ascending
     /// order and `desc` for descending order.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub order: Option<assistant_list_params::Order>,
 
+    /// A limit on the number of objects to be returned. Limit can range between 1 and
+    /// 100, and the default is 20.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub limit: Option<u32>,
 }
 
@@ -1390,10 +1316,244 @@ pub mod assistant_list_params {
     use super::*;
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Order {
         #[default]
         Asc,
         Desc,
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use futures::StreamExt;
+    use serde_json::json;
+    use super::*;
+    use crate::core::InMemoryTransport;
+
+    fn decode(event: &str, data: Value) -> AssistantStreamEvent {
+        serde_json::from_value(json!({"event": event, "data": data}))
+            .unwrap_or_else(|error| panic!("{event}: {error}"))
+    }
+
+    fn run(status: &str) -> Value {
+        json!({
+            "id": "run_123",
+            "object": "thread.run",
+            "created_at": 1710330640,
+            "assistant_id": "asst_123",
+            "thread_id": "thread_123",
+            "status": status,
+            "started_at": 1710330641,
+            "expires_at": null,
+            "cancelled_at": null,
+            "failed_at": null,
+            "completed_at": 1710330642,
+            "required_action": null,
+            "last_error": null,
+            "model": "gpt-4o",
+            "instructions": "You are a helpful assistant.",
+            "tools": [{"type": "code_interpreter"}, {"type": "file_search"}],
+            "metadata": {},
+            "incomplete_details": null,
+            "usage": {"prompt_tokens": 345, "completion_tokens": 11, "total_tokens": 356},
+            "temperature": 1.0,
+            "top_p": 1.0,
+            "max_prompt_tokens": null,
+            "max_completion_tokens": null,
+            "truncation_strategy": {"type": "auto", "last_messages": null},
+            "response_format": "auto",
+            "tool_choice": "auto",
+            "parallel_tool_calls": true,
+        })
+    }
+
+    fn run_step(step_details: Value) -> Value {
+        json!({
+            "id": "step_123",
+            "object": "thread.run.step",
+            "created_at": 1710330641,
+            "run_id": "run_123",
+            "assistant_id": "asst_123",
+            "thread_id": "thread_123",
+            "type": step_details["type"],
+            "status": "completed",
+            "cancelled_at": null,
+            "completed_at": 1710330642,
+            "expires_at": 1710331240,
+            "failed_at": null,
+            "last_error": null,
+            "step_details": step_details,
+            "usage": {"prompt_tokens": 139, "completion_tokens": 5, "total_tokens": 144},
+        })
+    }
+
+    fn message(status: &str, content: Value) -> Value {
+        json!({
+            "id": "msg_123",
+            "object": "thread.message",
+            "created_at": 1710330641,
+            "assistant_id": "asst_123",
+            "thread_id": "thread_123",
+            "run_id": "run_123",
+            "status": status,
+            "incomplete_details": null,
+            "incomplete_at": null,
+            "completed_at": null,
+            "role": "assistant",
+            "content": content,
+            "attachments": [],
+            "metadata": {},
+        })
+    }
+
+    #[test]
+    fn decodes_every_assistant_stream_event() {
+        let thread = json!({
+            "id": "thread_123",
+            "object": "thread",
+            "created_at": 1710330640,
+            "metadata": {},
+            "tool_resources": {"code_interpreter": {"file_ids": []}},
+        });
+        assert!(matches!(decode("thread.created", thread), AssistantStreamEvent::ThreadCreated(_)));
+
+        for status in ["queued", "in_progress", "completed", "incomplete", "failed", "cancelling", "cancelled", "expired"] {
+            let event = decode(&format!("thread.run.{status}"), run(status));
+            assert_eq!(event.event(), format!("thread.run.{status}"));
+        }
+
+        let mut requires_action = run("requires_action");
+        requires_action["required_action"] = json!({
+            "type": "submit_tool_outputs",
+            "submit_tool_outputs": {
+                "tool_calls": [{
+                    "id": "call_123",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"},
+                }],
+            },
+        });
+        match decode("thread.run.requires_action", requires_action) {
+            AssistantStreamEvent::ThreadRunRequiresAction(run) => {
+                let tool_calls = run.required_action.unwrap().submit_tool_outputs.tool_calls;
+                assert_eq!(tool_calls[0].function.name, "get_weather");
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        let message_creation = json!({"type": "message_creation", "message_creation": {"message_id": "msg_123"}});
+        assert!(matches!(decode("thread.run.step.created", run_step(message_creation)), AssistantStreamEvent::ThreadRunStepCreated(_)));
+        let tool_calls = json!({
+            "type": "tool_calls",
+            "tool_calls": [
+                {"id": "call_1", "type": "code_interpreter", "code_interpreter": {
+                    "input": "print(1)",
+                    "outputs": [{"type": "logs", "logs": "1"}, {"type": "image", "image": {"file_id": "file-1"}}],
+                }},
+                {"id": "call_2", "type": "file_search", "file_search": {}},
+                {"id": "call_3", "type": "function", "function": {"name": "get_weather", "arguments": "{}", "output": "22C"}},
+            ],
+        });
+        assert!(matches!(decode("thread.run.step.completed", run_step(tool_calls)), AssistantStreamEvent::ThreadRunStepCompleted(_)));
+
+        let step_delta = json!({
+            "id": "step_123",
+            "object": "thread.run.step.delta",
+            "delta": {"step_details": {"type": "tool_calls", "tool_calls": [
+                {"index": 0, "id": "call_1", "type": "code_interpreter", "code_interpreter": {"input": "", "outputs": []}},
+                {"index": 1, "type": "function", "function": {"arguments": "{\"loc"}},
+            ]}},
+        });
+        assert!(matches!(decode("thread.run.step.delta", step_delta), AssistantStreamEvent::ThreadRunStepDelta(_)));
+
+        assert!(matches!(decode("thread.message.created", message("in_progress", json!([]))), AssistantStreamEvent::ThreadMessageCreated(_)));
+        let message_delta = json!({
+            "id": "msg_123",
+            "object": "thread.message.delta",
+            "delta": {"content": [
+                {"index": 0, "type": "text", "text": {"value": "Hello", "annotations": []}},
+                {"index": 1, "type": "image_file", "image_file": {"file_id": "file-1"}},
+            ]},
+        });
+        assert!(matches!(decode("thread.message.delta", message_delta), AssistantStreamEvent::ThreadMessageDelta(_)));
+        let content = json!([
+            {"type": "text", "text": {"value": "Hello 【4:0†source】", "annotations": [
+                {"type": "file_citation", "text": "【4:0†source】", "start_index": 6, "end_index": 18, "file_citation": {"file_id": "file-1"}},
+                {"type": "file_path", "text": "sandbox:/a.csv", "start_index": 0, "end_index": 1, "file_path": {"file_id": "file-2"}},
+            ]}},
+            {"type": "image_file", "image_file": {"file_id": "file-3", "detail": "auto"}},
+        ]);
+        assert!(matches!(decode("thread.message.completed", message("completed", content)), AssistantStreamEvent::ThreadMessageCompleted(_)));
+
+        let error = json!({"code": "server_error", "message": "Sorry, something went wrong.", "param": null, "type": "server_error"});
+        assert!(matches!(decode("error", error), AssistantStreamEvent::ErrorEvent(_)));
+    }
+
+    fn assistant_page_json(ids: &[&str], has_more: bool) -> Value {
+        let data: Vec<Value> = ids.iter().map(|id| json!({
+            "id": id,
+            "object": "assistant",
+            "created_at": 1699009709,
+            "description": null,
+            "instructions": null,
+            "metadata": {},
+            "model": "gpt-4o",
+            "name": null,
+            "tools": [],
+        })).collect();
+        json!({"object": "list", "data": data, "first_id": ids.first(), "last_id": ids.last(), "has_more": has_more})
+    }
+
+    #[tokio::test]
+    async fn list_stream_sends_the_query_and_headers_with_every_page() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&assistant_page_json(&["asst_1", "asst_2"], true))
+            .push_json(&assistant_page_json(&["asst_3"], false));
+        let assistants = Assistants { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let page = assistants.list(AssistantListParams {
+            limit: Some(2),
+            order: Some(assistant_list_params::Order::Desc),
+            ..Default::default()
+        }, Some(RequestOptions {
+            headers: Some(HashMap::from([("X-Trace".to_string(), Some("abc".to_string()))])),
+            ..Default::default()
+        })).await.unwrap();
+        let ids: Vec<String> = page.into_stream().map(|assistant| assistant.unwrap().id).collect().await;
+        assert_eq!(ids, ["asst_1", "asst_2", "asst_3"]);
+
+        let requests = transport.requests();
+        let queries: Vec<HashMap<String, String>> = requests.iter()
+            .map(|request| request.url.query_pairs().into_owned().collect())
+            .collect();
+        assert_eq!(queries, [
+            HashMap::from([("limit".to_string(), "2".to_string()), ("order".to_string(), "desc".to_string())]),
+            HashMap::from([
+                ("after".to_string(), "asst_2".to_string()),
+                ("limit".to_string(), "2".to_string()),
+                ("order".to_string(), "desc".to_string()),
+            ]),
+        ]);
+        for request in &requests {
+            assert_eq!(request.headers["OpenAI-Beta"], "assistants=v2");
+            assert_eq!(request.headers["X-Trace"], "abc");
+        }
+    }
+
+    #[test]
+    fn keeps_unknown_events_as_received() {
+        let event = decode("thread.run.paused", json!({"id": "run_123"}));
+
+        assert!(matches!(&event, AssistantStreamEvent::Unknown { event, data } if event == "thread.run.paused" && data["id"] == "run_123"));
+        assert_eq!(serde_json::to_value(&event).unwrap(), json!({"event": "thread.run.paused", "data": {"id": "run_123"}}));
+    }
+
+    #[test]
+    fn fails_on_invalid_payloads_of_known_events() {
+        let result = serde_json::from_value::<AssistantStreamEvent>(json!({"event": "thread.run.created", "data": {"id": 1}}));
+
+        assert!(result.unwrap_err().to_string().starts_with("invalid `thread.run.created` event"));
+    }
+}
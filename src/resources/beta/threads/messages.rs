@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::Error;
 use std::rc::Rc;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -8,7 +8,7 @@ use crate::resource::{APIResource};
 use crate::core::{self, APIClient, FinalRequestOptions, Headers, RequestOptions};
 use crate::core::streaming::APIFuture;
 use crate::resources::beta::assistants as assistants_api;
-use crate::pagination::{CursorPage, CursorPageResponse, Page};
+use crate::pagination::{CursorItem, CursorPage, CursorPageResponse, Page};
 use crate::resources::chat::ChatCompletionContentPart;
 
 #[derive(Debug, Clone)]
@@ -108,10 +108,17 @@ impl Messages {
         &self,
         thread_id: &str,
         query: Option<MessageListParams>,
-        _options: Option<RequestOptions<MessageListParams>>,
-    ) -> Result<CursorPage<MessageListParams, Message>, Box<dyn Error>> {
+        options: Option<RequestOptions<MessageListParams>>,
+    ) -> Result<CursorPage<MessageListParams, Message>, Error> {
         let mut headers: Headers = HashMap::new();
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
+        if let Some(opts) = &options {
+            if let Some(hdrs) = &opts.headers {
+                for (key, value) in hdrs {
+                    headers.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
 
         let page_constructor = |
             client: APIResource,
@@ -127,7 +134,7 @@ impl Messages {
             Some(RequestOptions {
                 query: query,
                 headers: Some(headers),
-                ..Default::default()
+                ..options.unwrap_or_default()
             }),
         ).await
     }
@@ -360,7 +367,7 @@ pub mod file_path_delta_annotation {
     pub struct FilePath {
         /// The ID of the file that was generated.
         #[serde(skip_serializing_if = "Option::is_none")]
-        file_id: Option<String>,
+        pub file_id: Option<String>,
     }
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -405,25 +412,10 @@ pub struct ImageFileDeltaBlock {
     /// The index of the content part in the message.
     pub index: u32,
 
-    /// Always `image_file`.
-    #[serde(rename = "type")]
-    pub kind: image_file_delta_block::Type,
-
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_file: Option<ImageFileDelta>,
 }
 
-pub mod image_file_delta_block {
-    use super::*;
-
-    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(rename_all = "snake_case")]
-    pub enum Type {
-        #[default]
-        ImageFile,
-    }
-}
-
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ImageURL {
     /// The external URL of the image, must be a supported image types: jpeg, jpg, png,
@@ -455,26 +447,10 @@ pub struct ImageURLDeltaBlock {
     /// The index of the content part in the message.
     pub index: u32,
 
-    /// Always `image_url`.
-    #[serde(rename = "type")]
-    pub kind: image_url_delta_block::Type,
-
     #[serde(skip_serializing_if = "Option::is_none")]
     pub image_url: Option<ImageURLDelta>,
 }
 
-pub mod image_url_delta_block {
-    use super::*;
-
-    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(rename_all = "snake_case")]
-    pub enum Type {
-        #[default]
-        ImageUrl,
-    }
-
-}
-
 /// Represents a message within a
 /// [thread](https://platform.openai.com/docs/api-reference/threads).
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -531,6 +507,12 @@ pub struct Message {
     pub thread_id: String,
 }
 
+impl CursorItem for Message {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
 pub mod message {
     use super::*;
 
@@ -617,7 +599,7 @@ pub mod message {
         #[default]
         InProgress,
         Incomplete,
-        Complete,
+        Completed,
     }
 }
 
@@ -626,8 +608,9 @@ pub mod message {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageContent {
-    ImageFile(ImageFile),
-    ImageURL(ImageURL),
+    ImageFile{ image_file: ImageFile },
+    #[serde(rename = "image_url")]
+    ImageURL{ image_url: ImageURL },
     Text{ text: Text },
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -648,9 +631,11 @@ impl Default for MessageContent {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MessageContentDelta {
+    #[serde(rename = "image_file")]
     ImageFileDeltaBlock(ImageFileDeltaBlock),
     #[serde(rename = "text")]
     TextDeltaBlock(TextDeltaBlock),
+    #[serde(rename = "image_url")]
     ImageURLDeltaBlock(ImageURLDeltaBlock),
 }
 
@@ -665,7 +650,7 @@ pub mod message_deleted {
     use super::*;
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Object {
         #[serde(rename = "thread.message.deleted")]
         #[default]
@@ -682,14 +667,14 @@ pub struct MessageDelta {
 
     /// The entity that produced the message. One of `user` or `assistant`.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub role: Option<message_deleted::Object>,
+    pub role: Option<message_delta::Role>,
 }
 
 pub mod message_delta {
     use super::*;
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Role {
         #[default]
         User,
@@ -725,6 +710,7 @@ pub mod message_delta_event {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Text {
+    #[serde(default)]
     pub annotations: Vec<Annotation>,
 
     /// The data that makes up the text.
@@ -901,6 +887,13 @@ pub struct MessageUpdateParams {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct MessageListParams { // extends CursorPageParams
+    /// A cursor for use in pagination. `after` is an object ID that defines your place
+    /// in the list. For instance, if you make a list request and receive 100 objects,
+    /// ending with obj_foo, your subsequent call can include after=obj_foo in order to
+    /// fetch the next page of the list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+
     /// A cursor for use in pagination. `before` is an object ID that defines your place
     /// in the list. For instance, if you make a list request and receive 100 objects,
     /// ending with obj_foo, your subsequent call can include before=obj_foo in order to
@@ -916,13 +909,18 @@ pub struct MessageListParams { // extends CursorPageParams
     /// Filter messages by the run ID that generated them.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub run_id: Option<String>,
+
+    /// A limit on the number of objects to be returned. Limit can range between 1 and
+    /// 100, and the default is 20.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
 }
 
 pub mod message_list_params {
     use super::*;
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Order {
         #[default]
         Asc,
@@ -932,10 +930,47 @@ pub mod message_list_params {
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-#[serde(untagged, rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
 pub enum Detail {
     #[default]
     Auto,
     Low,
     High,
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn decodes_image_deltas() {
+        let event: MessageDeltaEvent = serde_json::from_value(json!({
+            "id": "msg_123",
+            "object": "thread.message.delta",
+            "delta": {"content": [
+                {"index": 1, "type": "image_file", "image_file": {"file_id": "file-1", "detail": "low"}},
+                {"index": 2, "type": "image_url", "image_url": {"url": "https://example.com/a.png"}},
+            ]},
+        })).unwrap();
+
+        let content = event.delta.content.unwrap();
+        match &content[0] {
+            MessageContentDelta::ImageFileDeltaBlock(block) => {
+                let image_file = block.image_file.as_ref().unwrap();
+                assert_eq!(block.index, 1);
+                assert_eq!(image_file.file_id.as_deref(), Some("file-1"));
+                assert!(matches!(image_file.detail, Some(Detail::Low)));
+            }
+            block => panic!("unexpected block: {block:?}"),
+        }
+        match &content[1] {
+            MessageContentDelta::ImageURLDeltaBlock(block) => {
+                assert_eq!(block.index, 2);
+                assert_eq!(block.image_url.as_ref().unwrap().url.as_deref(), Some("https://example.com/a.png"));
+            }
+            block => panic!("unexpected block: {block:?}"),
+        }
+        assert_eq!(serde_json::to_value(&content[0]).unwrap()["type"], "image_file");
+    }
+}
@@ -1,20 +1,23 @@
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::Error;
 use std::time::Duration;
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
 use crate::resource::APIResource;
 // use crate::core ::is_request_options;
-use crate::core::{self, FinalRequestOptions, Headers, RequestOptions};
+use crate::core::{self, APIClient, FinalRequestOptions, Headers, RequestOptions};
 use crate::core::streaming::APIFuture;
-use crate::library::assistant_stream::{AssistantStream, RunCreateParamsBaseStream, RunSubmitToolOutputsParamsStream};
+use crate::library::assistant_stream::{RunCreateParamsBaseStream, RunSubmitToolOutputsParamsStream};
+use crate::library::assistant_event_handler::AssistantEventHandler;
+use crate::library::run_tools::RunTools;
 use crate::resources::beta::threads::runs::runs as runs_api;
 use crate::resources::beta::assistants as assistants_api;
 use crate::resources::beta::threads::messages as messages_api;
 use crate::resources::beta::threads as threads_api;
 use crate::resources::beta::threads::runs::steps as steps_api;
-use crate::pagination::{CursorPage, CursorPageParams, CursorPageResponse, Page};
+use crate::pagination::{CursorItem, CursorPage, CursorPageParams, CursorPageResponse, Page};
 // use crate::streaming::{Stream};
 
 #[derive(Debug, Clone)]
@@ -119,7 +122,7 @@ impl Runs {
         thread_id: &str,
         query: Option<RunListParams>,
         options: Option<RequestOptions<RunListParams>>,
-    ) -> Result<CursorPage<RunListParams, Run>, Box<dyn Error>> {
+    ) -> Result<CursorPage<RunListParams, Run>, Error> {
         let mut headers: Headers = HashMap::new();
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
         if let Some(opts) = &options {
@@ -184,7 +187,7 @@ impl Runs {
         thread_id: &str,
         body: RunCreateParams, // RunCreateParamsNonStreaming
         options: Option<RequestOptions<RunCreateParams>>, // & { pollIntervalMs: Option<number> }>,
-    ) -> Result<Run, Box<dyn Error>> {
+    ) -> Result<Run, Error> {
         let run = self.create(thread_id, body, options.clone()).await?;
         self.poll(thread_id, &run.id, options).await
     }
@@ -203,12 +206,33 @@ impl Runs {
     /// A helper to poll a run status until it reaches a terminal state. More
     /// information on Run lifecycles can be found here:
     /// https://platform.openai.com/docs/assistants/how-it-works/runs-and-run-steps
+    ///
+    /// Waits `poll_interval_ms` between two retrieves, or the interval suggested by the
+    /// `openai-poll-after-ms` header, and fails with [`Error::PollTimeout`] once
+    /// `poll_timeout` elapses.
     pub async fn poll(
         &self,
         thread_id: &str,
         run_id: &str,
         options: Option<RequestOptions<RunCreateParams>>,
-    ) -> Result<Run, Box<dyn Error>> {
+    ) -> Result<Run, Error> {
+        let mut snapshots = Box::pin(self.poll_stream(thread_id, run_id, options));
+        let mut last = None;
+        while let Some(run) = snapshots.next().await {
+            last = Some(run?);
+        }
+        last.ok_or_else(|| Error::OpenAI("The run was not retrieved.".to_string()))
+    }
+
+    /// Like [`Runs::poll`], but yields every snapshot of the run retrieved while polling,
+    /// e.g. to show its progress from `queued` to `in_progress`. The last item is the run
+    /// in its terminal state, or the error that stopped the polling.
+    pub fn poll_stream(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        options: Option<RequestOptions<RunCreateParams>>,
+    ) -> impl Stream<Item=Result<Run, Error>> + 'static {
         let mut headers: Headers = HashMap::new();
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
         headers.insert("X-Stainless-Poll-Helper".to_string(), Some("true".to_string()));
@@ -221,45 +245,35 @@ impl Runs {
         }
 
         let mut options = options.unwrap_or_default();
-        let poll_interval_ms = options.poll_interval_ms.clone();
+        let poll_interval_ms = options.poll_interval_ms;
 
         if let Some(ms) = &poll_interval_ms {
-            headers.insert("X-Stainless-Custom-Poll-Interval".to_string(), Some(ms.clone().to_string()));
+            headers.insert("X-Stainless-Custom-Poll-Interval".to_string(), Some(ms.to_string()));
         }
 
         options.headers = Some(headers);
+        let poll_timeout = options.poll_timeout;
         let retrieve_options: RequestOptions<()> = options.convert(None);
 
-        loop {
-            let run = self.retrieve(thread_id, run_id, Some(retrieve_options.clone())).await?;
-            // let run = result.data;
-            // let response = result.response;
-
-            match run.status {
-                //If we are in any sort of intermediate state we poll
-                RunStatus::Queued | RunStatus::InProgress | RunStatus::Cancelling => {
-                    let sleep_interval = poll_interval_ms.unwrap_or(5000);
-
-                    // if poll_interval_ms.is_none() {
-                    //     let header_interval = response.headers.get("openai-poll - after - ms");
-                    //     if header_interval.is_some() {
-                    //         let header_interval_ms = parse_int(header_interval);
-                    //         if (!isNaN(header_interval_ms)) {
-                    //             sleep_interval = header_interval_ms;
-                    //         }
-                    //     }
-                    // }
-                    sleep(Duration::from_millis(sleep_interval as u64));
-                    // break
-                }
-                //We return the run in any terminal state.
-                RunStatus::RequiresAction | RunStatus::Incomplete |
-                RunStatus::Cancelled | RunStatus::Completed |
-                RunStatus::Failed | RunStatus::Expired => {
-                    return Ok(run);
-                }
+        let state = PollState {
+            runs: self.clone(),
+            thread_id: thread_id.to_string(),
+            run_id: run_id.to_string(),
+            deadline: poll_timeout.map(|timeout| Instant::now() + timeout),
+            poll_timeout,
+            poll_interval_ms,
+            retrieve_options,
+            next_retrieve_in: None,
+        };
+
+        futures::stream::unfold(Some(state), |state| async move {
+            let mut state = state?;
+            match state.next_snapshot().await {
+                Ok(run) if run.is_terminal() => Some((Ok(run), None)),
+                Ok(run) => Some((Ok(run), Some(state))),
+                Err(error) => Some((Err(error), None)),
             }
-        }
+        })
     }
 
     //   stream(thread_id: string, body: RunCreateParamsBaseStream, options: Option<Core.RequestOptions): AssistantStream >,
@@ -272,7 +286,7 @@ impl Runs {
         thread_id: &str,
         body: RunCreateParams,
         options: Option<RequestOptions<RunCreateParams>>,
-    ) ->  APIFuture<RunCreateParams, (), AssistantStream> {
+    ) ->  APIFuture<RunCreateParams, (), assistants_api::AssistantStreamEvent> {
         let mut headers: Headers = HashMap::new();
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
         // headers.insert("Content-Type".to_string(), Some("text/event-stream".to_string()));
@@ -296,54 +310,64 @@ impl Runs {
         )
     }
 
-    // /// When a run has the `status: "requires_action"` and `required_action.type` is
-    // /// `submit_tool_outputs`, this endpoint can be used to submit the outputs from the
-    // /// tool calls once they're all completed. All outputs must be submitted in a single
-    // /// request.
-    //   submitToolOutputs(
-    //     thread_id: string,
-    //     run_id: string,
-    //     body: RunSubmitToolOutputsParamsNonStreaming,// #[serde(skip_serializing_if = "Option::is_none")]
-    //     options: Option<Core.RequestOptions>,
-    //   ): APIPromise<Run>;
-    //   submitToolOutputs(
-    //     thread_id: string,
-    //     run_id: string,
-    //     body: RunSubmitToolOutputsParamsStreaming,// #[serde(skip_serializing_if = "Option::is_none")]
-    //     options: Option<Core.RequestOptions>,
-    //   ): APIPromise<Stream<assistants_api::AssistantStreamEvent>>;
-    //   submitToolOutputs(
-    //     thread_id: string,
-    //     run_id: string,
-    //     body: RunSubmitToolOutputsParamsBase,// #[serde(skip_serializing_if = "Option::is_none")]
-    //     options: Option<Core.RequestOptions>,
-    //   ): APIPromise<Stream<assistants_api::AssistantStreamEvent> | Run>;
-    //   submitToolOutputs(
-    //     thread_id: string,
-    //     run_id: string,
-    //     body: RunSubmitToolOutputsParams,// #[serde(skip_serializing_if = "Option::is_none")]
-    //     options: Option<Core.RequestOptions>,
-    //   ): APIPromise<Run> | APIPromise<Stream<assistants_api::AssistantStreamEvent>> {
-    //     return this._client.post(`/threads/${thread_id}/runs/${run_id}/submit_tool_outputs`, {
-    //       body,
-    //       ...options,
-    //       headers: { 'OpenAI-Beta': 'assistants=v2', ...options?.headers },
-    //       stream: body.stream ?? false,
-    //     }) as APIPromise<Run> | APIPromise<Stream<assistants_api::AssistantStreamEvent>>;
-    //   }
+    /// When a run has the `status: "requires_action"` and `required_action.type` is
+    /// `submit_tool_outputs`, this endpoint can be used to submit the outputs from the
+    /// tool calls once they're all completed. All outputs must be submitted in a single
+    /// request.
+    pub fn submit_tool_outputs(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        body: RunSubmitToolOutputsParams,
+        options: Option<RequestOptions<RunSubmitToolOutputsParams>>,
+    ) -> APIFuture<RunSubmitToolOutputsParams, Run, ()> {
+        // The run is returned as JSON, streams are sent by `submit_tool_outputs_stream`.
+        let body = RunSubmitToolOutputsParams { stream: None, ..body };
 
-    // /// A helper to submit a tool output to a run and poll for a terminal run state.
-    // /// More information on Run lifecycles can be found here:
-    // /// https://platform.openai.com/docs/assistants/how-it-works/runs-and-run-steps
-    //   async submitToolOutputsAndPoll(
-    //     thread_id: string,
-    //     run_id: string,
-    //     body: RunSubmitToolOutputsParamsNonStreaming,// #[serde(skip_serializing_if = "Option::is_none")]
-    //     options: Option<Core.RequestOptions & { pollIntervalMs: Option<number }>,
-    //   ): Promise<Run> {
-    //     const run = await this.submitToolOutputs(thread_id, run_id, body, options);
-    //     return await this.poll(thread_id, run.id, options);
-    //   }
+        let mut headers: Headers = HashMap::new();
+        headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
+        if let Some(opts) = &options {
+            if let Some(hdrs) = &opts.headers {
+                for (key, value) in hdrs {
+                    headers.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
+
+        self.client.clone().unwrap().lock().unwrap().post(
+            &format!("/threads/{thread_id}/runs/{run_id}/submit_tool_outputs"),
+            Some(RequestOptions {
+                body: Some(body),
+                headers: Some(headers),
+                stream: Some(false),
+                ..options.unwrap_or_default()
+            }),
+        )
+    }
+
+    /// A helper to submit a tool output to a run and poll for a terminal run state.
+    /// More information on Run lifecycles can be found here:
+    /// https://platform.openai.com/docs/assistants/how-it-works/runs-and-run-steps
+    pub async fn submit_tool_outputs_and_poll(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        body: RunSubmitToolOutputsAndPollParams,
+        options: Option<RequestOptions<RunSubmitToolOutputsParams>>,
+    ) -> Result<Run, Error> {
+        let body = RunSubmitToolOutputsParams {
+            tool_outputs: body.tool_outputs.into_iter()
+                .map(|tool_output| run_submit_tool_outputs_params::ToolOutput {
+                    output: tool_output.output,
+                    tool_call_id: tool_output.tool_call_id,
+                })
+                .collect(),
+            stream: None,
+        };
+
+        let run = self.submit_tool_outputs(thread_id, run_id, body, options.clone()).await?;
+        self.poll(thread_id, &run.id, options.map(|options| options.convert(None))).await
+    }
 
     // /// Submit the tool outputs from a previous run and stream the run to a terminal
     // /// state. More information on Run lifecycles can be found here:
@@ -372,7 +396,7 @@ impl Runs {
         run_id: &str,
         body: RunSubmitToolOutputsParams,
         options: Option<RequestOptions<RunSubmitToolOutputsParams>>,
-    ) ->  APIFuture<RunSubmitToolOutputsParams, (), AssistantStream> {
+    ) ->  APIFuture<RunSubmitToolOutputsParams, (), assistants_api::AssistantStreamEvent> {
         let mut headers: Headers = HashMap::new();
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
         // headers.insert("Content-Type".to_string(), Some("text/event-stream".to_string()));
@@ -396,6 +420,75 @@ impl Runs {
         )
     }
 
+    /// A helper to create a run and poll it to a terminal state, calling the functions of
+    /// `tools` and submitting their outputs every time the run requires action.
+    pub async fn create_and_poll_with_tools(
+        &self,
+        thread_id: &str,
+        body: RunCreateParams,
+        tools: &RunTools,
+        options: Option<RequestOptions<RunCreateParams>>,
+    ) -> Result<Run, Error> {
+        let run = self.create(thread_id, body, options.clone()).await?;
+        self.poll_with_tools(thread_id, &run.id, tools, options).await
+    }
+
+    /// Like [`Runs::poll`], calling the functions of `tools` and submitting their outputs
+    /// every time the run requires action, until it reaches a terminal state.
+    pub async fn poll_with_tools(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        tools: &RunTools,
+        options: Option<RequestOptions<RunCreateParams>>,
+    ) -> Result<Run, Error> {
+        let mut run = self.poll(thread_id, run_id, options.clone()).await?;
+        while let Some(tool_calls) = run.required_tool_calls() {
+            let body = RunSubmitToolOutputsParams {
+                tool_outputs: tools.call_all(tool_calls).await,
+                stream: None,
+            };
+            let run_id = self.submit_tool_outputs(thread_id, &run.id, body, Self::submit_options(&options)).await?.id;
+            run = self.poll(thread_id, &run_id, options.clone()).await?;
+        }
+        Ok(run)
+    }
+
+    /// A helper to stream a run to a terminal state through `handler`, calling the functions
+    /// of `tools` and streaming the submission of their outputs every time the run requires
+    /// action. Pass `&mut ()` to ignore the events.
+    pub async fn stream_with_tools<H: AssistantEventHandler + ?Sized>(
+        &self,
+        thread_id: &str,
+        body: RunCreateParams,
+        tools: &RunTools,
+        handler: &mut H,
+        options: Option<RequestOptions<RunCreateParams>>,
+    ) -> Result<Run, Error> {
+        let body = RunCreateParams { stream: Some(true), ..body };
+        let mut run = self.stream(thread_id, body, options.clone()).with_handler(handler).await?;
+        while let Some(tool_calls) = run.required_tool_calls() {
+            let body = RunSubmitToolOutputsParams {
+                tool_outputs: tools.call_all(tool_calls).await,
+                stream: Some(true),
+            };
+            run = self.submit_tool_outputs_stream(thread_id, &run.id, body, Self::submit_options(&options))
+                .with_handler(handler)
+                .await?;
+        }
+        Ok(run)
+    }
+
+    /// The options of the tool outputs submitted by the `*_with_tools` helpers, each with
+    /// its own idempotency key.
+    fn submit_options(
+        options: &Option<RequestOptions<RunCreateParams>>,
+    ) -> Option<RequestOptions<RunSubmitToolOutputsParams>> {
+        options.clone().map(|options| RequestOptions {
+            idempotency_key: None,
+            ..options.convert(None)
+        })
+    }
 }
 
 /// Tool call objects
@@ -436,6 +529,72 @@ pub mod required_action_function_tool_call {
     }
 }
 
+/// The progress of [`Runs::poll_stream`].
+struct PollState {
+    runs: Runs,
+    thread_id: String,
+    run_id: String,
+    deadline: Option<Instant>,
+    poll_timeout: Option<Duration>,
+    poll_interval_ms: Option<u32>,
+    retrieve_options: RequestOptions<()>,
+    /// The delay before the next retrieve, `None` before the first one.
+    next_retrieve_in: Option<Duration>,
+}
+
+impl PollState {
+    async fn next_snapshot(&mut self) -> Result<Run, Error> {
+        let signal = self.retrieve_options.signal.clone();
+
+        if let Some(delay) = self.next_retrieve_in {
+            let delay = match self.deadline {
+                Some(deadline) => delay.min(deadline.saturating_duration_since(Instant::now())),
+                None => delay,
+            };
+            APIClient::abortable(signal.clone(), async {
+                sleep(delay).await;
+                Ok(())
+            }).await?;
+        }
+
+        if let (Some(deadline), Some(poll_timeout)) = (self.deadline, self.poll_timeout) {
+            if Instant::now() >= deadline {
+                return Err(Error::PollTimeout(poll_timeout));
+            }
+        }
+        if signal.as_ref().is_some_and(|signal| signal.is_cancelled()) {
+            return Err(Error::UserAbort);
+        }
+
+        let (run, response) = self.runs
+            .retrieve(&self.thread_id, &self.run_id, Some(self.retrieve_options.clone()))
+            .with_response()
+            .await?;
+
+        if run.is_terminal() {
+            if run.usage.is_some() {
+                let client = self.runs.client.clone().unwrap();
+                let client = client.lock().unwrap();
+                if let Ok(run) = serde_json::to_value(&run) {
+                    client.metrics.record_run_usage(&client.base_url, &run);
+                }
+            }
+        } else {
+            // Honor the interval suggested by the server, unless the caller set one.
+            let header_interval_ms = response.headers
+                .get("openai-poll-after-ms")
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+            let sleep_interval = self.poll_interval_ms.map(u64::from)
+                .or(header_interval_ms)
+                .unwrap_or(5000);
+            self.next_retrieve_in = Some(Duration::from_millis(sleep_interval));
+        }
+
+        Ok(run)
+    }
+}
+
 /// Represents an execution run on a
 /// [thread](https://platform.openai.com/docs/api-reference/threads).
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -566,6 +725,27 @@ pub struct Run {
     pub top_p: Option<f32>,
 }
 
+impl CursorItem for Run {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Run {
+    /// Whether the run stopped progressing: `requires_action` or one of the final states.
+    pub fn is_terminal(&self) -> bool {
+        !matches!(self.status, RunStatus::Queued | RunStatus::InProgress | RunStatus::Cancelling)
+    }
+
+    /// The function calls to answer, when the run is `requires_action`.
+    pub fn required_tool_calls(&self) -> Option<&[RequiredActionFunctionToolCall]> {
+        match (&self.status, &self.required_action) {
+            (RunStatus::RequiresAction, Some(required_action)) => Some(&required_action.submit_tool_outputs.tool_calls),
+            _ => None,
+        }
+    }
+}
+
 pub mod run {
     use super::*;
 
@@ -583,7 +763,7 @@ pub mod run {
         use super::*;
 
         #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-        #[serde(untagged, rename_all = "snake_case")]
+        #[serde(rename_all = "snake_case")]
         pub enum Reason {
             #[default]
             MaxCompletionTokens,
@@ -612,7 +792,7 @@ pub mod run {
         use super::*;
 
         #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-        #[serde(untagged, rename_all = "snake_case")]
+        #[serde(rename_all = "snake_case")]
         pub enum Code {
             #[default]
             ServerError,
@@ -992,6 +1172,13 @@ pub struct RunUpdateParams {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct RunListParams { //extends CursorPageParams
+    /// A cursor for use in pagination. `after` is an object ID that defines your place
+    /// in the list. For instance, if you make a list request and receive 100 objects,
+    /// ending with obj_foo, your subsequent call can include after=obj_foo in order to
+    /// fetch the next page of the list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+
     /// A cursor for use in pagination. `before` is an object ID that defines your place
     /// in the list. For instance, if you make a list request and receive 100 objects,
     /// ending with obj_foo, your subsequent call can include before=obj_foo in order to
@@ -1003,13 +1190,18 @@ pub struct RunListParams { //extends CursorPageParams
     /// order and `desc` for descending order.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub order: Option<run_list_params::Order>,
+
+    /// A limit on the number of objects to be returned. Limit can range between 1 and
+    /// 100, and the default is 20.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
 }
 
 pub mod run_list_params {
     use super::*;
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Order {
         #[default]
         Asc,
@@ -1742,12 +1934,12 @@ pub mod run_submit_tool_outputs_params {
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
     pub struct ToolOutput {
         /// The output of the tool call to be submitted to continue the run.
-        // #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(skip_serializing_if = "Option::is_none")]
         pub output: Option<String>,
 
         /// The ID of the tool call in the `required_action` object within the run object
         /// the output is being submitted for.
-        // #[serde(skip_serializing_if = "Option::is_none")]
+        #[serde(skip_serializing_if = "Option::is_none")]
         pub tool_call_id: Option<String>,
     }
 }
@@ -1791,4 +1983,352 @@ pub mod run_submit_tool_outputs_stream_params {
         #[serde(skip_serializing_if = "Option::is_none")]
         pub tool_call_id: Option<String>,
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use serde_json::json;
+    use super::*;
+    use crate::core::InMemoryTransport;
+    use crate::test_support::run_json;
+
+    fn page_json(ids: &[&str], has_more: bool) -> Value {
+        let data: Vec<Value> = ids.iter().map(|id| {
+            let mut run = run_json("completed");
+            run["id"] = json!(id);
+            run
+        }).collect();
+        json!({"object": "list", "data": data, "first_id": ids.first(), "last_id": ids.last(), "has_more": has_more})
+    }
+
+    #[tokio::test]
+    async fn list_pages_follow_the_id_of_the_last_run() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&page_json(&["run_1", "run_2"], true))
+            .push_json(&page_json(&["run_3"], false));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let page = runs.list("thread_abc", Some(RunListParams {
+            limit: Some(2),
+            order: Some(run_list_params::Order::Desc),
+            ..Default::default()
+        }), None).await.unwrap();
+        assert!(page.has_next_page());
+
+        let next_page = page.get_next_page().await.unwrap();
+        assert_eq!(next_page.get_paginated_items()[0].id, "run_3");
+        assert!(!next_page.has_next_page());
+        assert!(next_page.get_next_page().await.is_err());
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 2);
+        let query: HashMap<_, _> = requests[1].url.query_pairs().into_owned().collect();
+        assert_eq!(query, HashMap::from([
+            ("after".to_string(), "run_2".to_string()),
+            ("limit".to_string(), "2".to_string()),
+            ("order".to_string(), "desc".to_string()),
+        ]));
+    }
+
+    #[tokio::test]
+    async fn list_stream_fetches_pages_up_to_the_item_cap() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&page_json(&["run_1", "run_2"], true))
+            .push_json(&page_json(&["run_3", "run_4"], true))
+            .push_json(&page_json(&["run_5"], false));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let page = runs.list("thread_abc", None, None).await.unwrap();
+        let all: Vec<String> = page.iter_all().map(|run| run.unwrap().id).collect().await;
+        assert_eq!(all, ["run_1", "run_2", "run_3", "run_4", "run_5"]);
+
+        transport.push_json(&page_json(&["run_3", "run_4"], true));
+        let capped: Vec<String> = page.into_stream().max_items(3).map(|run| run.unwrap().id).collect().await;
+        assert_eq!(capped, ["run_1", "run_2", "run_3"]);
+        assert_eq!(transport.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn list_stream_prefetches_and_yields_page_errors_last() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&page_json(&["run_1", "run_2"], true))
+            .push_response(500, &[], r#"{"error":{"message":"boom"}}"#);
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let mut stream = runs.list("thread_abc", None, None).await.unwrap().into_stream().prefetch(true);
+        assert_eq!(stream.next().await.unwrap().unwrap().id, "run_1");
+        assert_eq!(transport.requests().len(), 2);
+        assert_eq!(stream.next().await.unwrap().unwrap().id, "run_2");
+        assert!(stream.next().await.unwrap().is_err());
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn list_stream_prefetches_a_single_page_ahead() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&page_json(&["run_1", "run_2"], true))
+            .push_json(&page_json(&["run_3", "run_4"], true))
+            .push_json(&page_json(&["run_5", "run_6"], true))
+            .push_json(&page_json(&["run_7"], false));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let mut stream = runs.list("thread_abc", None, None).await.unwrap().into_stream().prefetch(true);
+        let mut requests = Vec::new();
+        while let Some(run) = stream.next().await {
+            requests.push((run.unwrap().id, transport.requests().len()));
+        }
+        assert_eq!(requests, [
+            ("run_1".to_string(), 2),
+            ("run_2".to_string(), 2),
+            ("run_3".to_string(), 3),
+            ("run_4".to_string(), 3),
+            ("run_5".to_string(), 4),
+            ("run_6".to_string(), 4),
+            ("run_7".to_string(), 4),
+        ]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn poll_yields_snapshots_and_honors_the_suggested_interval() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_response(200, &[("openai-poll-after-ms", "250")], run_json("queued").to_string())
+            .push_json(&run_json("in_progress"))
+            .push_json(&run_json("completed"));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+        let started_at = Instant::now();
+
+        let statuses: Vec<RunStatus> = runs.poll_stream("thread_abc", "run_abc", None)
+            .map(|run| run.unwrap().status)
+            .collect()
+            .await;
+
+        assert!(matches!(statuses.as_slice(), [RunStatus::Queued, RunStatus::InProgress, RunStatus::Completed]));
+        // 250ms suggested by the server, then the default interval of 5 seconds.
+        assert_eq!(started_at.elapsed(), Duration::from_millis(5250));
+        assert_eq!(transport.requests()[0].headers["X-Stainless-Poll-Helper"], "true");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn poll_fails_once_the_poll_timeout_elapses() {
+        let transport = Arc::new(InMemoryTransport::new());
+        for _ in 0..5 {
+            transport.push_response(200, &[("openai-poll-after-ms", "250")], run_json("in_progress").to_string());
+        }
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let result = runs.poll("thread_abc", "run_abc", Some(RequestOptions {
+            poll_interval_ms: Some(1000),
+            poll_timeout: Some(Duration::from_millis(2500)),
+            ..Default::default()
+        })).await;
+
+        assert!(matches!(result, Err(Error::PollTimeout(_))));
+        // The custom interval takes precedence over the one suggested by the server.
+        assert_eq!(transport.requests().len(), 3);
+        assert_eq!(transport.requests()[0].headers["X-Stainless-Custom-Poll-Interval"], "1000");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn submit_tool_outputs_and_poll_resolves_required_actions() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&run_json("queued"))
+            .push_json(&run_json("completed"));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let run = runs.submit_tool_outputs_and_poll("thread_abc", "run_abc", RunSubmitToolOutputsAndPollParams {
+            tool_outputs: vec![run_submit_tool_outputs_and_poll_params::ToolOutput {
+                output: Some("22C".to_string()),
+                tool_call_id: Some("call_abc".to_string()),
+            }],
+        }, None).await.unwrap();
+
+        assert!(matches!(run.status, RunStatus::Completed));
+        let requests = transport.requests();
+        assert_eq!(requests[0].url.path(), "/threads/thread_abc/runs/run_abc/submit_tool_outputs");
+        let body: Value = serde_json::from_slice(requests[0].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body, json!({"tool_outputs": [{"output": "22C", "tool_call_id": "call_abc"}]}));
+        assert_eq!(requests[1].url.path(), "/threads/thread_abc/runs/run_abc");
+    }
+
+    #[tokio::test]
+    async fn submit_tool_outputs_returns_a_run_even_when_asked_for_a_stream() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&run_json("queued"));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let run = runs.submit_tool_outputs("thread_abc", "run_abc", RunSubmitToolOutputsParams {
+            tool_outputs: vec![run_submit_tool_outputs_params::ToolOutput {
+                output: Some("22C".to_string()),
+                tool_call_id: Some("call_abc".to_string()),
+            }],
+            stream: Some(true),
+        }, Some(RequestOptions { stream: Some(true), ..Default::default() })).await.unwrap();
+
+        assert!(matches!(run.status, RunStatus::Queued));
+        let body: Value = serde_json::from_slice(transport.requests()[0].body.as_ref().unwrap()).unwrap();
+        assert!(body.get("stream").is_none());
+    }
+
+    fn requires_action_json() -> Value {
+        let mut run = run_json("requires_action");
+        run["required_action"] = json!({
+            "type": "submit_tool_outputs",
+            "submit_tool_outputs": {"tool_calls": [
+                {"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"location\":\"Paris\"}"}},
+                {"id": "call_2", "type": "function", "function": {"name": "get_time", "arguments": "{}"}},
+            ]},
+        });
+        run
+    }
+
+    fn weather_tools() -> RunTools {
+        let mut tools = RunTools::new();
+        tools.function("get_weather", |args: Value| async move {
+            Ok(format!("22C in {}", args["location"].as_str().unwrap_or_default()))
+        });
+        tools
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn poll_with_tools_submits_outputs_until_the_run_ends() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&requires_action_json())
+            .push_json(&run_json("queued"))
+            .push_json(&run_json("completed"));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let run = runs.poll_with_tools("thread_abc", "run_abc", &weather_tools(), None).await.unwrap();
+
+        assert_eq!(run.status, RunStatus::Completed);
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[1].url.path(), "/threads/thread_abc/runs/run_abc/submit_tool_outputs");
+        let body: Value = serde_json::from_slice(requests[1].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body, json!({"tool_outputs": [
+            {"output": "22C in Paris", "tool_call_id": "call_1"},
+            {"output": "{\"error\":\"Unknown function `get_time`\"}", "tool_call_id": "call_2"},
+        ]}));
+    }
+
+    #[tokio::test]
+    async fn stream_with_tools_streams_the_submitted_outputs() {
+        let transport = Arc::new(InMemoryTransport::new());
+        let sse = |event: &str, data: Value| format!("event: {event}\ndata: {data}\n\n");
+        transport
+            .push_response(200, &[("content-type", "text/event-stream")], sse("thread.run.requires_action", requires_action_json()))
+            .push_response(200, &[("content-type", "text/event-stream")], sse("thread.run.completed", run_json("completed")));
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let run = runs.stream_with_tools("thread_abc", RunCreateParams {
+            assistant_id: "asst_abc".to_string(),
+            ..Default::default()
+        }, &weather_tools(), &mut (), None).await.unwrap();
+
+        assert_eq!(run.status, RunStatus::Completed);
+        let requests = transport.requests();
+        let body: Value = serde_json::from_slice(requests[0].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(requests[1].url.path(), "/threads/thread_abc/runs/run_abc/submit_tool_outputs");
+        let body: Value = serde_json::from_slice(requests[1].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["tool_outputs"][0]["output"], "22C in Paris");
+    }
+
+    #[tokio::test]
+    async fn stream_decodes_events_by_name() {
+        let transport = Arc::new(InMemoryTransport::new());
+        let body = format!(
+            "event: thread.run.created\ndata: {}\n\n\
+             event: thread.message.delta\ndata: {}\n\n\
+             event: thread.run.paused\ndata: {{\"id\":\"run_abc\"}}\n\n\
+             event: thread.run.completed\ndata: {}\n\n\
+             event: done\ndata: [DONE]\n\n",
+            run_json("queued"),
+            json!({"id": "msg_abc", "object": "thread.message.delta", "delta": {"content": [{"index": 0, "type": "text", "text": {"value": "Hi"}}]}}),
+            run_json("completed"),
+        );
+        transport.push_response(200, &[("content-type", "text/event-stream")], body);
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let events: Vec<_> = runs.stream("thread_abc", RunCreateParams {
+            assistant_id: "asst_abc".to_string(),
+            stream: Some(true),
+            ..Default::default()
+        }, None).into_stream().map(Result::unwrap).collect().await;
+
+        let names: Vec<_> = events.iter().map(|event| event.event()).collect();
+        assert_eq!(names, ["thread.run.created", "thread.message.delta", "thread.run.paused", "thread.run.completed"]);
+        assert!(matches!(&events[0], assistants_api::AssistantStreamEvent::ThreadRunCreated(run) if run.status == RunStatus::Queued));
+        assert!(matches!(&events[2], assistants_api::AssistantStreamEvent::Unknown { data, .. } if data["id"] == "run_abc"));
+    }
+
+    #[derive(Default)]
+    struct Printer {
+        text: String,
+        calls: Vec<String>,
+    }
+
+    #[async_trait::async_trait(?Send)]
+    impl AssistantEventHandler for Printer {
+        async fn on_text_delta(&mut self, delta: &messages_api::TextDelta, _snapshot: &messages_api::Text) {
+            self.text.push_str(delta.value.as_deref().unwrap_or_default());
+        }
+
+        async fn on_text_done(&mut self, text: &messages_api::Text) {
+            self.calls.push(format!("text done: {}", text.value));
+        }
+
+        async fn on_end(&mut self, run: &Run) {
+            self.calls.push(format!("end: {:?}", run.status));
+        }
+    }
+
+    #[tokio::test]
+    async fn submit_tool_outputs_stream_drives_event_handlers() {
+        let transport = Arc::new(InMemoryTransport::new());
+        let message = json!({
+            "id": "msg_abc",
+            "object": "thread.message",
+            "created_at": 1699063291,
+            "thread_id": "thread_abc",
+            "role": "assistant",
+            "content": [],
+        });
+        let text_delta = |value: &str| json!({
+            "id": "msg_abc",
+            "object": "thread.message.delta",
+            "delta": {"content": [{"index": 0, "type": "text", "text": {"value": value}}]},
+        });
+        let mut completed_message = message.clone();
+        completed_message["content"] = json!([{"type": "text", "text": {"value": "It is 22C", "annotations": []}}]);
+        let body = format!(
+            "event: thread.message.created\ndata: {message}\n\n\
+             event: thread.message.delta\ndata: {}\n\n\
+             event: thread.message.delta\ndata: {}\n\n\
+             event: thread.message.completed\ndata: {completed_message}\n\n\
+             event: thread.run.completed\ndata: {}\n\n",
+            text_delta("It is"),
+            text_delta(" 22C"),
+            run_json("completed"),
+        );
+        transport.push_response(200, &[("content-type", "text/event-stream")], body);
+        let runs = Runs { client: Some(APIClient::resource_with_transport(&transport)) };
+        let mut printer = Printer::default();
+
+        let run = runs.submit_tool_outputs_stream("thread_abc", "run_abc", RunSubmitToolOutputsParams {
+            stream: Some(true),
+            ..Default::default()
+        }, None).with_handler(&mut printer).await.unwrap();
+
+        assert_eq!(run.status, RunStatus::Completed);
+        assert_eq!(printer.text, "It is 22C");
+        assert_eq!(printer.calls, ["text done: It is 22C", "end: Completed"]);
+    }
+}
@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::error::Error;
+use crate::error::Error;
 use std::rc::Rc;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -8,7 +8,7 @@ use crate::resource::APIResource;
 use crate::core::{self, APIClient, FinalRequestOptions, Headers};
 use crate::core::streaming::APIFuture;
 use crate::resources::beta::threads::runs::steps as steps_api;
-use crate::pagination::{CursorPage, CursorPageParams, CursorPageResponse, Page};
+use crate::pagination::{CursorItem, CursorPage, CursorPageParams, CursorPageResponse, Page};
 
 #[derive(Debug, Clone)]
 pub struct Steps {
@@ -50,10 +50,17 @@ impl Steps {
         thread_id: &str,
         run_id: &str,
         query: StepListParams,
-        _options: Option<core::RequestOptions<StepListParams>>,
-    ) -> Result<CursorPage<StepListParams, RunStep>, Box<dyn Error>> {
+        options: Option<core::RequestOptions<StepListParams>>,
+    ) -> Result<CursorPage<StepListParams, RunStep>, Error> {
         let mut headers: Headers = HashMap::new();
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
+        if let Some(opts) = &options {
+            if let Some(hdrs) = &opts.headers {
+                for (key, value) in hdrs {
+                    headers.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
 
         let page_constructor = |
             client: APIResource,
@@ -66,9 +73,10 @@ impl Steps {
         self.client.clone().unwrap().lock().unwrap().get_api_list(
             &format!("/threads/{thread_id}/runs/{run_id}/steps"),
             page_constructor,
-            Some(core::RequestOptions::<StepListParams> {
+            Some(core::RequestOptions {
                 query: Some(query),
-                ..Default::default()
+                headers: Some(headers),
+                ..options.unwrap_or_default()
             }),
         ).await
     }
@@ -129,7 +137,7 @@ pub mod code_interpreter_output_image {
         /// The [file](https://platform.openai.com/docs/api-reference/files) ID of the
         /// image.
         #[serde(skip_serializing_if = "Option::is_none")]
-        file_id: Option<String>,
+        pub file_id: Option<String>,
     }
 }
 
@@ -206,7 +214,7 @@ pub mod code_interpreter_tool_call {
             pub struct Image {
                 /// The [file](https://platform.openai.com/docs/api-reference/files) ID of the
                 /// image.
-                file_id: String,
+                pub file_id: String,
             }
 
             #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -368,15 +376,15 @@ pub mod function_tool_call {
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
     pub struct Function {
         /// The arguments passed to the function.
-        arguments: String,
+        pub arguments: String,
 
         /// The name of the function.
-        name: String,
+        pub name: String,
 
         /// The output of the function. This will be `null` if the outputs have not been
         /// [submitted](https://platform.openai.com/docs/api-reference/runs/submitToolOutputs)
         /// yet.
-        output: Option<String>,
+        pub output: Option<String>,
     }
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -448,7 +456,7 @@ pub mod message_creation_step_details {
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
     pub struct MessageCreation {
         /// The ID of the message that was created by this run step.
-        message_id: String,
+        pub message_id: String,
     }
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
@@ -523,6 +531,12 @@ pub struct RunStep {
     pub usage: Option<run_step::Usage>,
 }
 
+impl CursorItem for RunStep {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
 pub mod run_step {
     use super::*;
 
@@ -541,7 +555,7 @@ pub mod run_step {
         use serde::{Deserialize, Serialize};
 
         #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-        #[serde(untagged, rename_all = "snake_case")]
+        #[serde(rename_all = "snake_case")]
         pub enum Code {
             #[default]
             ServerError,
@@ -564,7 +578,7 @@ pub mod run_step {
     }
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Status {
         #[default]
         InProgress,
@@ -596,7 +610,7 @@ pub mod run_step {
     }
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Object {
         #[default]
         #[serde(rename = "thread.run.step")]
@@ -686,7 +700,7 @@ pub mod run_step_delta_message_delta {
 
 /// Details of the Code Interpreter tool call the run step was involved in.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+#[serde(untagged, rename_all = "snake_case")]
 pub enum ToolCall {
     CodeInterpreter(CodeInterpreterToolCall),
     FileSearch(FileSearchToolCall),
@@ -765,6 +779,7 @@ pub mod tool_calls_step_details {
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct StepListParams {
+    #[serde(flatten)]
     pub cursor_page: CursorPageParams,
 
     /// A cursor for use in pagination. `before` is an object ID that defines your place
@@ -784,7 +799,7 @@ pub mod step_list_params {
     use super::*;
 
     #[derive(Default, Debug, Clone, Serialize, Deserialize)]
-    #[serde(untagged, rename_all = "snake_case")]
+    #[serde(rename_all = "snake_case")]
     pub enum Order {
         #[default]
         Asc,
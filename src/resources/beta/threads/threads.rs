@@ -164,15 +164,15 @@ impl Threads {
         &self,
         body: ThreadCreateAndRunParams,
         options: Option<core::RequestOptions<ThreadCreateAndRunParams>>,
-    ) -> APIFuture<ThreadCreateAndRunParams, Thread, ()> {
+    ) -> APIFuture<ThreadCreateAndRunParams, runs_api::Run, ()> {
         let stream = body.stream.unwrap_or(false);
         let mut headers: Headers = HashMap::new();
         // headers: { 'OpenAI-Beta': 'assistants=v2', ...options?.headers },
         headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
-        if let Some(opts) = options {
-            if let Some(hdrs) = opts.headers {
+        if let Some(opts) = &options {
+            if let Some(hdrs) = &opts.headers {
                 for (key, value) in hdrs {
-                    headers.insert(key, value);
+                    headers.insert(key.to_owned(), value.to_owned());
                 }
             }
         }
@@ -183,54 +183,58 @@ impl Threads {
                 body: Some(body),
                 stream: Some(stream),
                 headers: Some(headers),
-                ..Default::default()
+                ..options.unwrap_or_default()
             }),
         )
     }
 
-    // /// A helper to create a thread, start a run and then poll for a terminal state.
-    // /// More information on Run lifecycles can be found here:
-    // /// https://platform.openai.com/docs/assistants/how-it-works/runs-and-run-steps
-    // // async create_and_run_poll(
-    // //     body: ThreadCreateAndRunParamsNonStreaming,
-    // //     options: Option<Core.RequestOptions & { pollIntervalMs: Option<number }>,
-    // // ): Promise<Threads.Run> {
-    // //     const run = await this.createAndRun(body, options),
-    // //     return await this.runs.poll(run.thread_id, run.id, options),
-    // // }
-    // pub async fn create_and_run_poll(
-    //     &self,
-    //     body: ThreadCreateAndRunParams,
-    //     options: Option<core::RequestOptions<ThreadCreateAndRunParams>>,
-    // ) -> Result<threads::Runs, Box<dyn Error>> {
-    //     let run = self.create_and_run(body, options).await?;
-    //     // self.runs.poll
-    //     runs_api::Runs::poll(
-    //         &self.openai.as_ref().unwrap().borrow().runs,
-    //         run.thread_id,
-    //         run.id,
-    //         options,
-    //     ).await
-    // }
-
-    // /// Create a thread and stream the run back
-    // // createAndRunStream(
-    // //     body: ThreadCreateAndRunParamsBaseStream,
-    // //     options: Option<Core.RequestOptions>,
-    // // ): AssistantStream {
-    // //     return AssistantStream.createThreadAssistantStream(body, this._client.beta.threads, options),
-    // // }
-    // pub async fn create_and_run_stream(
-    //     &self,
-    //     body: ThreadCreateAndRunParams,
-    //     options: Option<core::RequestOptions>,
-    // ) -> Result<AssistantStream, Box<dyn Error>> {
-    //     streaming::Stream::create_thread_assistant_stream(
-    //         body,
-    //         &self.client.as_ref().unwrap().borrow().client.beta.threads,
-    //         options,
-    //     )
-    // }
+    /// A helper to create a thread, start a run and then poll for a terminal state.
+    /// More information on Run lifecycles can be found here:
+    /// https://platform.openai.com/docs/assistants/how-it-works/runs-and-run-steps
+    pub async fn create_and_run_poll(
+        &self,
+        body: ThreadCreateAndRunPollParams,
+        options: Option<core::RequestOptions<ThreadCreateAndRunPollParams>>,
+    ) -> Result<runs_api::Run, crate::Error> {
+        // The poll params are the non-streaming create and run params.
+        let body: ThreadCreateAndRunParams = serde_json::from_value(serde_json::to_value(body)?)?;
+        let run = self.create_and_run(
+            ThreadCreateAndRunParams { stream: None, ..body },
+            options.clone().map(|options| options.convert(None)),
+        ).await?;
+
+        self.runs.poll(&run.thread_id, &run.id, options.map(|options| options.convert(None))).await
+    }
+
+    /// Create a thread and stream the run back.
+    pub fn create_and_run_stream(
+        &self,
+        body: ThreadCreateAndRunStreamParams,
+        options: Option<core::RequestOptions<ThreadCreateAndRunStreamParams>>,
+    ) -> APIFuture<ThreadCreateAndRunStreamParams, (), assistants_api::AssistantStreamEvent> {
+        let mut headers: Headers = HashMap::new();
+        headers.insert("OpenAI-Beta".to_string(), Some("assistants=v2".to_string()));
+        if let Some(opts) = &options {
+            if let Some(hdrs) = &opts.headers {
+                for (key, value) in hdrs {
+                    headers.insert(key.to_owned(), value.to_owned());
+                }
+            }
+        }
+
+        self.client.clone().unwrap().lock().unwrap().post(
+            "/threads/runs",
+            Some(core::RequestOptions {
+                body: Some(ThreadCreateAndRunStreamParams {
+                    stream: Some(true),
+                    ..body
+                }),
+                headers: Some(headers),
+                stream: Some(true),
+                ..options.unwrap_or_default()
+            }),
+        )
+    }
 }
 
 // #[derive(Debug, Deserialize, Serialize)]
@@ -1487,7 +1491,7 @@ pub struct ThreadCreateAndRunStreamParams {
     /// The ID of the
     /// [assistant](https://platform.openai.com/docs/api-reference/assistants) to use to
     /// execute this run.
-    assistant_id: String,
+    pub assistant_id: String,
     /// Override the default system message of the assistant. This is useful for
     /// modifying the behavior on a per-run basis.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -1605,6 +1609,10 @@ pub struct ThreadCreateAndRunStreamParams {
     /// control the intial context window of the run.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub truncation_strategy: Option<thread_create_and_run_stream_params::TruncationStrategy>,
+
+    /// Always sent as `true` by `Threads::create_and_run_stream`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream: Option<bool>,
 }
 
 pub mod thread_create_and_run_stream_params {
@@ -1914,3 +1922,55 @@ pub mod threads {
     pub use messages_api::MessageUpdateParams;
     pub use messages_api::MessageListParams;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use futures::StreamExt;
+    use serde_json::json;
+    use super::*;
+    use crate::core::InMemoryTransport;
+    use crate::test_support::run_json;
+
+    fn threads_with(transport: &Arc<InMemoryTransport>) -> Threads {
+        let mut threads = Threads::new();
+        threads.set_client(APIClient::resource_with_transport(transport));
+        threads
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn create_and_run_poll_returns_the_terminal_run() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&run_json("queued")).push_json(&run_json("completed"));
+        let threads = threads_with(&transport);
+
+        let run = threads.create_and_run_poll(ThreadCreateAndRunPollParams {
+            assistant_id: "asst_abc".to_string(),
+            ..Default::default()
+        }, None).await.unwrap();
+
+        assert!(matches!(run.status, runs_api::RunStatus::Completed));
+        let requests = transport.requests();
+        assert_eq!(requests[0].url.path(), "/threads/runs");
+        assert_eq!(requests[0].headers["OpenAI-Beta"], "assistants=v2");
+        let body: Value = serde_json::from_slice(requests[0].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body, json!({"assistant_id": "asst_abc"}));
+        assert_eq!(requests[1].url.path(), "/threads/thread_abc/runs/run_abc");
+    }
+
+    #[tokio::test]
+    async fn create_and_run_stream_asks_for_a_stream() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_response(200, &[("content-type", "text/event-stream")], "data: [DONE]\n\n");
+        let threads = threads_with(&transport);
+
+        let events: Vec<_> = threads.create_and_run_stream(ThreadCreateAndRunStreamParams {
+            assistant_id: "asst_abc".to_string(),
+            ..Default::default()
+        }, None).into_stream().collect().await;
+
+        assert!(events.is_empty());
+        let body: Value = serde_json::from_slice(transport.requests()[0].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body, json!({"assistant_id": "asst_abc", "stream": true}));
+    }
+}
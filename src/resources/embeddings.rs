@@ -0,0 +1,315 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Deserializer, Serialize};
+use crate::core::RequestOptions;
+use crate::core::streaming::APIFuture;
+use crate::error::Error;
+use crate::resource::APIResource;
+
+/// The maximum number of inputs the API embeds in a single request.
+pub const MAX_INPUTS_PER_REQUEST: usize = 2048;
+
+#[derive(Default, Debug, Clone)]
+pub struct Embeddings {
+    pub client: Option<APIResource>,
+}
+
+impl Embeddings {
+    pub fn new() -> Self {
+        Embeddings {
+            client: None,
+        }
+    }
+
+    /// Creates an embedding vector representing the input text.
+    ///
+    /// The embeddings are requested as base64 unless `encoding_format` is set, and are
+    /// always decoded to floats.
+    pub fn create(
+        &self,
+        mut body: EmbeddingCreateParams,
+        options: Option<RequestOptions<EmbeddingCreateParams>>,
+    ) -> APIFuture<EmbeddingCreateParams, CreateEmbeddingResponse, ()> {
+        body.encoding_format.get_or_insert(EncodingFormat::Base64);
+
+        self.client.clone().unwrap().lock().unwrap().post(
+            "/embeddings",
+            Some(RequestOptions {
+                body: Some(body),
+                ..options.unwrap_or_default()
+            }),
+        )
+    }
+
+    /// Like [`Embeddings::create`], splitting the inputs into requests of at most
+    /// `batch_size` inputs, capped to [`MAX_INPUTS_PER_REQUEST`].
+    ///
+    /// The requests are sent one after the other. The embeddings of every batch are
+    /// returned in the order of the inputs, with their `index` in the whole input, and the
+    /// usage is the sum of the usage of every request.
+    pub async fn create_batched(
+        &self,
+        body: EmbeddingCreateParams,
+        batch_size: usize,
+        options: Option<RequestOptions<EmbeddingCreateParams>>,
+    ) -> Result<CreateEmbeddingResponse, Error> {
+        let batch_size = batch_size.clamp(1, MAX_INPUTS_PER_REQUEST);
+        let batches: Vec<Input> = match &body.input {
+            Input::StringArray(inputs) => inputs.chunks(batch_size).map(|batch| Input::StringArray(batch.to_vec())).collect(),
+            Input::TokenArrays(inputs) => inputs.chunks(batch_size).map(|batch| Input::TokenArrays(batch.to_vec())).collect(),
+            input => vec![input.clone()],
+        };
+
+        let mut response: Option<CreateEmbeddingResponse> = None;
+        for input in batches {
+            let body = EmbeddingCreateParams { input, ..body.clone() };
+            // Each batch is a different request, that can't share the idempotency key.
+            let options = options.clone().map(|options| RequestOptions { idempotency_key: None, ..options });
+            let mut batch = self.create(body, options).await?;
+            // The embeddings of a response are not guaranteed to be in the order of its inputs.
+            batch.data.sort_by_key(|embedding| embedding.index);
+
+            match response.as_mut() {
+                None => response = Some(batch),
+                Some(response) => {
+                    let offset = response.data.len();
+                    response.data.extend(batch.data.into_iter().map(|embedding| Embedding {
+                        index: embedding.index + offset,
+                        ..embedding
+                    }));
+                    response.usage.prompt_tokens += batch.usage.prompt_tokens;
+                    response.usage.total_tokens += batch.usage.total_tokens;
+                }
+            }
+        }
+
+        Ok(response.unwrap_or_default())
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct CreateEmbeddingResponse {
+    /// The list of embeddings generated by the model.
+    pub data: Vec<Embedding>,
+
+    /// The name of the model used to generate the embedding.
+    pub model: String,
+
+    /// The object type, which is always "list".
+    pub object: String,
+
+    /// The usage information for the request.
+    pub usage: create_embedding_response::Usage,
+}
+
+pub mod create_embedding_response {
+    use super::*;
+
+    /// The usage information for the request.
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    pub struct Usage {
+        /// The number of tokens used by the prompt.
+        pub prompt_tokens: u32,
+
+        /// The total number of tokens used by the request.
+        pub total_tokens: u32,
+    }
+}
+
+/// Represents an embedding vector returned by embedding endpoint.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct Embedding {
+    /// The embedding vector, which is a list of floats. The length of vector depends on
+    /// the model as listed in the
+    /// [embedding guide](https://platform.openai.com/docs/guides/embeddings).
+    ///
+    /// Embeddings received as base64 are decoded.
+    #[serde(deserialize_with = "deserialize_embedding")]
+    pub embedding: Vec<f32>,
+
+    /// The index of the embedding in the list of embeddings.
+    pub index: usize,
+
+    /// The object type, which is always "embedding".
+    pub object: String,
+}
+
+/// Deserializes an embedding sent as a list of floats, or as the base64 of their
+/// little-endian bytes.
+fn deserialize_embedding<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum EncodedEmbedding {
+        Float(Vec<f32>),
+        Base64(String),
+    }
+
+    match EncodedEmbedding::deserialize(deserializer)? {
+        EncodedEmbedding::Float(embedding) => Ok(embedding),
+        EncodedEmbedding::Base64(embedding) => {
+            let bytes = STANDARD.decode(embedding).map_err(serde::de::Error::custom)?;
+            if bytes.len() % 4 != 0 {
+                return Err(serde::de::Error::custom("base64 embedding is not a list of 32-bit floats"));
+            }
+            Ok(bytes.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect())
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingCreateParams {
+    /// Input text to embed, encoded as a string or array of tokens. To embed multiple
+    /// inputs in a single request, pass an array of strings or array of token arrays.
+    /// The input must not exceed the max input tokens for the model (8192 tokens for
+    /// `text-embedding-ada-002`), cannot be an empty string, and any array must be 2048
+    /// dimensions or less.
+    pub input: Input,
+
+    /// ID of the model to use. You can use the
+    /// [List models](https://platform.openai.com/docs/api-reference/models/list) API to
+    /// see all of your available models, or see our
+    /// [Model overview](https://platform.openai.com/docs/models/overview) for
+    /// descriptions of them.
+    pub model: String,
+
+    /// The number of dimensions the resulting output embeddings should have. Only
+    /// supported in `text-embedding-3` and later models.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimensions: Option<u32>,
+
+    /// The format to return the embeddings in. Can be either `float` or
+    /// [`base64`](https://pypi.org/project/pybase64/).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding_format: Option<EncodingFormat>,
+
+    /// A unique identifier representing your end-user, which can help OpenAI to monitor
+    /// and detect abuse.
+    /// [Learn more](https://platform.openai.com/docs/guides/safety-best-practices/end-user-ids).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Input {
+    String(String),
+    StringArray(Vec<String>),
+    TokenArray(Vec<u32>),
+    TokenArrays(Vec<Vec<u32>>),
+}
+
+impl Default for Input {
+    fn default() -> Self {
+        Input::String(String::new())
+    }
+}
+
+impl From<&str> for Input {
+    fn from(input: &str) -> Self {
+        Input::String(input.to_string())
+    }
+}
+
+impl From<String> for Input {
+    fn from(input: String) -> Self {
+        Input::String(input)
+    }
+}
+
+impl From<Vec<String>> for Input {
+    fn from(inputs: Vec<String>) -> Self {
+        Input::StringArray(inputs)
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncodingFormat {
+    #[default]
+    Float,
+    Base64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::core::{APIClient, InMemoryTransport};
+
+    fn base64(embedding: &[f32]) -> String {
+        STANDARD.encode(embedding.iter().flat_map(|float| float.to_le_bytes()).collect::<Vec<u8>>())
+    }
+
+    fn response_json(embeddings: &[&[f32]], prompt_tokens: u32) -> Value {
+        let data: Vec<Value> = embeddings.iter().enumerate().map(|(index, embedding)| json!({
+            "object": "embedding",
+            "index": index,
+            "embedding": base64(embedding),
+        })).collect();
+        json!({
+            "object": "list",
+            "data": data,
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": prompt_tokens, "total_tokens": prompt_tokens},
+        })
+    }
+
+    #[tokio::test]
+    async fn create_requests_and_decodes_base64_embeddings() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&response_json(&[&[0.5, -1.25, 3.0]], 2));
+        let embeddings = Embeddings { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let response = embeddings.create(EmbeddingCreateParams {
+            input: "hello world".into(),
+            model: "text-embedding-3-small".to_string(),
+            dimensions: Some(3),
+            ..Default::default()
+        }, None).await.unwrap();
+
+        assert_eq!(response.data[0].embedding, [0.5, -1.25, 3.0]);
+        let body: Value = serde_json::from_slice(transport.requests()[0].body.as_ref().unwrap()).unwrap();
+        assert_eq!(body, json!({
+            "input": "hello world",
+            "model": "text-embedding-3-small",
+            "dimensions": 3,
+            "encoding_format": "base64",
+        }));
+    }
+
+    #[test]
+    fn embeddings_decode_from_floats() {
+        let embedding: Embedding = serde_json::from_value(json!({
+            "object": "embedding",
+            "index": 0,
+            "embedding": [0.5, -1.25],
+        })).unwrap();
+        assert_eq!(embedding.embedding, [0.5, -1.25]);
+    }
+
+    #[tokio::test]
+    async fn create_batched_reassembles_batches_in_order() {
+        let transport = Arc::new(InMemoryTransport::new());
+        let mut out_of_order = response_json(&[&[3.0], &[4.0]], 2);
+        out_of_order["data"].as_array_mut().unwrap().reverse();
+        transport
+            .push_json(&response_json(&[&[1.0], &[2.0]], 4))
+            .push_json(&out_of_order);
+        let embeddings = Embeddings { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let response = embeddings.create_batched(EmbeddingCreateParams {
+            input: Input::TokenArrays(vec![vec![1, 2], vec![3, 4], vec![5, 6], vec![7, 8]]),
+            model: "text-embedding-3-small".to_string(),
+            ..Default::default()
+        }, 2, None).await.unwrap();
+
+        let data: Vec<(usize, Vec<f32>)> = response.data.into_iter().map(|embedding| (embedding.index, embedding.embedding)).collect();
+        assert_eq!(data, [(0, vec![1.0]), (1, vec![2.0]), (2, vec![3.0]), (3, vec![4.0])]);
+        assert_eq!(response.usage.total_tokens, 6);
+        let inputs: Vec<Value> = transport.requests().iter()
+            .map(|request| serde_json::from_slice::<Value>(request.body.as_ref().unwrap()).unwrap()["input"].clone())
+            .collect();
+        assert_eq!(inputs, [json!([[1, 2], [3, 4]]), json!([[5, 6], [7, 8]])]);
+    }
+}
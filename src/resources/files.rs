@@ -0,0 +1,403 @@
+use std::time::Duration;
+use reqwest::Response;
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Instant};
+use crate::core::{APIClient, FilePart, FinalRequestOptions, MultipartForm, RequestOptions};
+use crate::core::streaming::APIFuture;
+use crate::error::Error;
+use crate::pagination::{CursorItem, CursorPage, CursorPageResponse, Page};
+use crate::resource::APIResource;
+
+/// The interval between two retrievals of `Files::wait_for_processing`.
+const DEFAULT_POLL_INTERVAL_MS: u32 = 5000;
+
+/// How long `Files::wait_for_processing` waits for a file by default.
+const DEFAULT_POLL_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Default, Debug, Clone)]
+pub struct Files {
+    pub client: Option<APIResource>,
+}
+
+impl Files {
+    pub fn new() -> Self {
+        Files {
+            client: None,
+        }
+    }
+
+    /// Upload a file that can be used across various endpoints. Individual files can be
+    /// up to 512 MB, and the size of all files uploaded by one organization can be up
+    /// to 100 GB.
+    ///
+    /// The Assistants API supports files up to 2 million tokens and of specific file
+    /// types. See the
+    /// [Assistants Tools guide](https://platform.openai.com/docs/assistants/tools) for
+    /// details.
+    ///
+    /// ```ignore
+    /// let file = openai.files.create(FileCreateParams {
+    ///     file: FilePart::from_path("report.pdf").await?,
+    ///     purpose: FilePurpose::Assistants,
+    /// }, None).await?;
+    /// ```
+    pub fn create(
+        &self,
+        body: FileCreateParams,
+        options: Option<RequestOptions<()>>,
+    ) -> APIFuture<(), FileObject, ()> {
+        let form = MultipartForm::from_fields(&body).file("file", body.file);
+
+        self.client.clone().unwrap().lock().unwrap().post(
+            "/files",
+            Some(RequestOptions {
+                multipart: Some(form),
+                ..options.unwrap_or_default()
+            }),
+        )
+    }
+
+    /// Returns information about a specific file.
+    pub fn retrieve(
+        &self,
+        file_id: &str,
+        options: Option<RequestOptions<()>>,
+    ) -> APIFuture<(), FileObject, ()> {
+        self.client.clone().unwrap().lock().unwrap().get(
+            &format!("/files/{file_id}"),
+            Some(options.unwrap_or_default()),
+        )
+    }
+
+    /// Returns a list of files that belong to the user's organization.
+    pub async fn list(
+        &self,
+        query: Option<FileListParams>,
+        options: Option<RequestOptions<FileListParams>>,
+    ) -> Result<CursorPage<FileListParams, FileObject>, Error> {
+        let page_constructor = |
+            client: APIResource,
+            body: CursorPageResponse<FileObject>,
+            options: FinalRequestOptions<FileListParams>,
+        | {
+            CursorPage::new(client, body, options)
+        };
+
+        let client = self.client.clone().unwrap().lock().unwrap().clone();
+        client.get_api_list(
+            "/files",
+            page_constructor,
+            Some(RequestOptions {
+                query,
+                ..options.unwrap_or_default()
+            }),
+        ).await
+    }
+
+    /// Delete a file.
+    pub fn del(
+        &self,
+        file_id: &str,
+        options: Option<RequestOptions<()>>,
+    ) -> APIFuture<(), FileDeleted, ()> {
+        self.client.clone().unwrap().lock().unwrap().delete(
+            &format!("/files/{file_id}"),
+            Some(options.unwrap_or_default()),
+        )
+    }
+
+    /// Returns the contents of the specified file.
+    ///
+    /// The body of the response is not read, stream it with `Response::bytes_stream`.
+    pub async fn content(
+        &self,
+        file_id: &str,
+        options: Option<RequestOptions<()>>,
+    ) -> Result<Response, Error> {
+        let future: APIFuture<(), (), ()> = self.client.clone().unwrap().lock().unwrap().get(
+            &format!("/files/{file_id}/content"),
+            Some(RequestOptions {
+                binary_response: Some(true),
+                ..options.unwrap_or_default()
+            }),
+        );
+        future.as_response().await
+    }
+
+    /// Retrieves the file until it is processed or failed to be.
+    ///
+    /// The file is retrieved every `poll_interval_ms` of the options, 5 seconds by default,
+    /// for at most their `poll_timeout`, 30 minutes by default.
+    pub async fn wait_for_processing(
+        &self,
+        file_id: &str,
+        options: Option<RequestOptions<()>>,
+    ) -> Result<FileObject, Error> {
+        let options = options.unwrap_or_default();
+        let poll_interval = Duration::from_millis(options.poll_interval_ms.unwrap_or(DEFAULT_POLL_INTERVAL_MS) as u64);
+        let poll_timeout = options.poll_timeout.unwrap_or(DEFAULT_POLL_TIMEOUT);
+        let deadline = Instant::now() + poll_timeout;
+
+        loop {
+            let file = self.retrieve(file_id, Some(options.clone())).await?;
+            if file.status.as_ref().is_some_and(file_object::Status::is_terminal) {
+                return Ok(file);
+            }
+            if Instant::now() + poll_interval > deadline {
+                return Err(Error::PollTimeout(poll_timeout));
+            }
+            APIClient::abortable(options.signal.clone(), async {
+                sleep(poll_interval).await;
+                Ok(())
+            }).await?;
+        }
+    }
+}
+
+/// The `File` object represents a document that has been uploaded to OpenAI.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct FileObject {
+    /// The file identifier, which can be referenced in the API endpoints.
+    pub id: String,
+
+    /// The size of the file, in bytes.
+    pub bytes: u64,
+
+    /// The Unix timestamp (in seconds) for when the file was created.
+    pub created_at: u64,
+
+    /// The name of the file.
+    pub filename: String,
+
+    /// The object type, which is always `file`.
+    pub object: String,
+
+    /// The intended purpose of the file. Supported values are `assistants`,
+    /// `assistants_output`, `batch`, `batch_output`, `fine-tune`, `fine-tune-results`
+    /// and `vision`.
+    pub purpose: String,
+
+    /// Deprecated. The current status of the file, which can be either `uploaded`,
+    /// `processed`, or `error`.
+    pub status: Option<file_object::Status>,
+
+    /// Deprecated. For details on why a fine-tuning training file failed validation, see
+    /// the `error` field on `fine_tuning.job`.
+    pub status_details: Option<String>,
+}
+
+impl CursorItem for FileObject {
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+pub mod file_object {
+    use super::*;
+
+    #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Status {
+        #[default]
+        Uploaded,
+        Processed,
+        Error,
+    }
+
+    impl Status {
+        /// Whether the processing of the file is over.
+        pub fn is_terminal(&self) -> bool {
+            matches!(self, Status::Processed | Status::Error)
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct FileDeleted {
+    pub id: String,
+    pub deleted: bool,
+    pub object: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileCreateParams {
+    /// The File object (not file name) to be uploaded.
+    #[serde(skip)]
+    pub file: FilePart,
+
+    /// The intended purpose of the uploaded file.
+    ///
+    /// Use "assistants" for
+    /// [Assistants](https://platform.openai.com/docs/api-reference/assistants) and
+    /// [Message](https://platform.openai.com/docs/api-reference/messages) files,
+    /// "vision" for Assistants image file inputs, "batch" for
+    /// [Batch API](https://platform.openai.com/docs/guides/batch), and "fine-tune" for
+    /// [Fine-tuning](https://platform.openai.com/docs/api-reference/fine-tuning).
+    pub purpose: FilePurpose,
+}
+
+/// The intended purpose of an uploaded file.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FilePurpose {
+    #[default]
+    #[serde(rename = "assistants")]
+    Assistants,
+    #[serde(rename = "batch")]
+    Batch,
+    #[serde(rename = "fine-tune")]
+    FineTune,
+    #[serde(rename = "vision")]
+    Vision,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct FileListParams {
+    /// A cursor for use in pagination. `after` is an object ID that defines your place
+    /// in the list. For instance, if you make a list request and receive 100 objects,
+    /// ending with obj_foo, your subsequent call can include after=obj_foo in order to
+    /// fetch the next page of the list.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+
+    /// A limit on the number of objects to be returned. Limit can range between 1 and
+    /// 10,000, and the default is 10,000.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+
+    /// Sort order by the `created_at` timestamp of the objects. `asc` for ascending
+    /// order and `desc` for descending order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<file_list_params::Order>,
+
+    /// Only return files with the given purpose.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub purpose: Option<String>,
+}
+
+pub mod file_list_params {
+    use super::*;
+
+    #[derive(Default, Debug, Clone, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Order {
+        #[default]
+        Asc,
+        Desc,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use futures::StreamExt;
+    use serde_json::{json, Value};
+    use super::*;
+    use crate::core::{CancellationToken, InMemoryTransport};
+
+    fn file_json(status: &str) -> Value {
+        json!({
+            "id": "file-abc",
+            "object": "file",
+            "bytes": 5,
+            "created_at": 1699061776,
+            "filename": "notes.txt",
+            "purpose": "assistants",
+            "status": status,
+        })
+    }
+
+    #[tokio::test]
+    async fn create_uploads_a_multipart_form() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&file_json("uploaded"));
+        let files = Files { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let file = files.create(FileCreateParams {
+            file: FilePart::from_bytes("hello", "notes.txt").content_type("text/plain"),
+            purpose: FilePurpose::Assistants,
+        }, None).await.unwrap();
+        assert_eq!(file.id, "file-abc");
+
+        let request = &transport.requests()[0];
+        let content_type = request.headers["Content-Type"].to_str().unwrap();
+        let boundary = content_type.strip_prefix("multipart/form-data; boundary=").unwrap();
+        let body = String::from_utf8(request.body.clone().unwrap().to_vec()).unwrap();
+        assert_eq!(body, format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nassistants\r\n\
+             --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\nContent-Type: text/plain\r\n\r\nhello\r\n\
+             --{boundary}--\r\n"
+        ));
+    }
+
+    #[tokio::test]
+    async fn content_streams_the_binary_response() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_response(200, &[("content-type", "application/octet-stream")], &b"\x00\x01binary"[..]);
+        let files = Files { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let response = files.content("file-abc", None).await.unwrap();
+        let chunks: Vec<_> = response.bytes_stream().collect().await;
+        let content: Vec<u8> = chunks.into_iter().flat_map(|chunk| chunk.unwrap()).collect();
+        assert_eq!(content, b"\x00\x01binary");
+
+        let request = &transport.requests()[0];
+        assert_eq!(request.url.path(), "/files/file-abc/content");
+        assert_eq!(request.headers["Accept"], "application/binary");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_processing_polls_until_the_file_is_processed() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport
+            .push_json(&file_json("uploaded"))
+            .push_json(&file_json("uploaded"))
+            .push_json(&file_json("processed"));
+        let files = Files { client: Some(APIClient::resource_with_transport(&transport)) };
+        let started_at = Instant::now();
+
+        let file = files.wait_for_processing("file-abc", None).await.unwrap();
+
+        assert_eq!(file.status, Some(file_object::Status::Processed));
+        assert_eq!(started_at.elapsed(), Duration::from_secs(10));
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_processing_gives_up_after_the_poll_timeout() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&file_json("uploaded")).push_json(&file_json("uploaded"));
+        let files = Files { client: Some(APIClient::resource_with_transport(&transport)) };
+
+        let error = files.wait_for_processing("file-abc", Some(RequestOptions {
+            poll_interval_ms: Some(1000),
+            poll_timeout: Some(Duration::from_millis(1500)),
+            ..Default::default()
+        })).await.unwrap_err();
+
+        assert!(matches!(error, Error::PollTimeout(timeout) if timeout == Duration::from_millis(1500)));
+        assert_eq!(transport.requests().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn wait_for_processing_stops_when_the_signal_is_cancelled() {
+        let transport = Arc::new(InMemoryTransport::new());
+        transport.push_json(&file_json("uploaded"));
+        let files = Files { client: Some(APIClient::resource_with_transport(&transport)) };
+        let signal = CancellationToken::new();
+        let cancel = signal.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(1)).await;
+            cancel.cancel();
+        });
+        let started_at = Instant::now();
+
+        let error = files.wait_for_processing("file-abc", Some(RequestOptions {
+            signal: Some(signal),
+            ..Default::default()
+        })).await.unwrap_err();
+
+        assert!(matches!(error, Error::UserAbort));
+        assert_eq!(started_at.elapsed(), Duration::from_secs(1));
+        assert_eq!(transport.requests().len(), 1);
+    }
+}
@@ -1,3 +1,5 @@
 pub mod completions;
 pub mod chat;
+pub mod embeddings;
+pub mod files;
 pub mod beta;
\ No newline at end of file
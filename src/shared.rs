@@ -2,13 +2,13 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Default, Debug, Clone, Deserialize, Serialize)]
 pub struct ErrorObject {
-    code: Option<String>,
-    message: String,
-    param: Option<String>,
+    pub code: Option<String>,
+    pub message: String,
+    pub param: Option<String>,
     #[serde(rename = "type")]
-    kind: String,
+    pub kind: String,
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize)]
@@ -0,0 +1,19 @@
+//! Fixtures shared by the tests of several modules.
+
+use serde_json::{json, Value};
+
+/// A run of `thread_abc` in the given status.
+pub(crate) fn run_json(status: &str) -> Value {
+    json!({
+        "id": "run_abc",
+        "object": "thread.run",
+        "assistant_id": "asst_abc",
+        "thread_id": "thread_abc",
+        "created_at": 1699063290,
+        "instructions": "",
+        "model": "gpt-4o",
+        "parallel_tool_calls": true,
+        "status": status,
+        "tools": [],
+    })
+}